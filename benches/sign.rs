@@ -0,0 +1,85 @@
+// solsign is a binary only crate, so its source is included here in order to bench it
+#[allow(dead_code)]
+mod solsign
+{
+    use criterion::{BatchSize, Criterion};
+
+    // A transaction with as many signers as a transaction can have, along with a signature for each signer
+    fn fully_signable_transaction() -> (Transaction, Vec<(Pubkey, ed25519_dalek::Signature)>)
+    {
+        let keypairs : Vec<ed25519_dalek::Keypair> =
+            (1..=MAXIMUM_ED25519_SIGNATURES_COUNT).map(|seed| keypair_from_seed(&[seed; 32]).unwrap()).collect();
+
+        let tx = Transaction {
+            signed_read_write_addresses : keypairs
+                .iter()
+                .map(|keypair| PubkeyWithSignature { pubkey : Pubkey(keypair.public.to_bytes()), signature : None })
+                .collect(),
+            signed_read_only_addresses : vec![],
+            unsigned_read_write_addresses : vec![],
+            unsigned_read_only_addresses : vec![SYSTEM_PROGRAM_ADDRESS],
+            recent_blockhash : Some(Sha256Digest([1_u8; 32])),
+            instructions : vec![]
+        };
+
+        let mut message = vec![];
+        tx.message(&mut message).unwrap();
+
+        let signatures =
+            keypairs.iter().map(|keypair| (Pubkey(keypair.public.to_bytes()), keypair.sign(&message))).collect();
+
+        (tx, signatures)
+    }
+
+    // The alternative to sign_all's linear search of the signatures for each slot: a map from each signer's pubkey to
+    // its signature slots, built once, from which each signature is placed
+    fn sign_all_with_map(
+        tx : &mut Transaction,
+        signatures : &[(Pubkey, ed25519_dalek::Signature)]
+    )
+    {
+        let mut slots = HashMap::<&Pubkey, Vec<&mut Option<ed25519_dalek::Signature>>>::new();
+
+        for PubkeyWithSignature { pubkey, signature } in
+            tx.signed_read_write_addresses.iter_mut().chain(tx.signed_read_only_addresses.iter_mut())
+        {
+            slots.entry(pubkey).or_default().push(signature);
+        }
+
+        for (pubkey, signature) in signatures {
+            for slot in slots.remove(pubkey).unwrap_or_default() {
+                *slot = Some(*signature);
+            }
+        }
+    }
+
+    pub fn sign(c : &mut Criterion)
+    {
+        let (tx, signatures) = fully_signable_transaction();
+
+        c.bench_function("sign each of 18 signers", |b| {
+            b.iter_batched_ref(
+                || tx.clone(),
+                |tx| {
+                    for (pubkey, signature) in &signatures {
+                        tx.sign(pubkey, *signature).unwrap();
+                    }
+                },
+                BatchSize::SmallInput
+            )
+        });
+
+        c.bench_function("sign_all 18 signers", |b| {
+            b.iter_batched_ref(|| tx.clone(), |tx| tx.sign_all(&signatures), BatchSize::SmallInput)
+        });
+
+        c.bench_function("sign_all with a pubkey map 18 signers", |b| {
+            b.iter_batched_ref(|| tx.clone(), |tx| sign_all_with_map(tx, &signatures), BatchSize::SmallInput)
+        });
+    }
+
+    include!("../src/main.rs");
+}
+
+criterion::criterion_group!(benches, solsign::sign);
+criterion::criterion_main!(benches);
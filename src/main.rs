@@ -1,8 +1,8 @@
 use ed25519_dalek::Signer;
-use std::collections::HashMap;
-use std::io::Write;
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, IsTerminal, Read, Write};
 
-/**
+/*
  * Accepts Base64 encoded Solana transactions.
  *
  * Inputs:
@@ -18,37 +18,293 @@ use std::io::Write;
  *    - Signature of the transaction, i.e. the fee payer signature (if tx is complete)
  **/
 
-
 /*      4567890123456789012345678901234567890123456789012345678901234567890123456789 */
 #[rustfmt::skip]
 fn usage_string() -> String
 {
     "\nUsage: solsign [--help]\n\
-    \x20      solsign [--no-prompt] [KEY_FILE]...\n\n\
+    \x20      solsign [--no-prompt] [--no-challenge] [--no-key-prompt] [--assume-yes]\n\
+    \x20              [--password-fd N] [--insecure-echo] [--concatenated]\n\
+    \x20              [--allow-empty-blockhash] [--count] [--binary] [--no-wrap]\n\
+    \x20              [--wrap-width N] [--indent N] [--length-prefixed] [--cli-json]\n\
+    \x20              [--program-filter PROGRAM]...\n\
+    \x20              [--sol] [--require-complete] [--explain]\n\
+    \x20              [--signature-format FORMAT] [--signature-only]\n\
+    \x20              [--signature PUBKEY=SIGNATURE]... [--signer PUBKEY]...\n\
+    \x20              [--policy-command COMMAND] [--allow-destination PUBKEY]...\n\
+    \x20              [--max-lamports N] [--max-token-amount N] [--max-signers N]\n\
+    \x20              [--seed-hex SEED]... [--derivation-path PATH]\n\
+    \x20              [--pubkey-prefix PREFIX]\n\
+    \x20              [--mnemonic-file PATH [--passphrase-file PATH]]\n\
+    \x20              [--keys-from-stdin] [--keyring PATH] [KEY_FILE]...\n\
+    \x20      solsign --sign-message [--no-prompt] [KEY_FILE]...\n\
+    \x20      solsign --ata MINT [--no-prompt] [KEY_FILE]...\n\
+    \x20      solsign --show-pubkeys [--no-prompt] [KEY_FILE]...\n\
+    \x20      solsign --generate PATH\n\
+    \x20      solsign --addresses [--no-prompt]\n\
+    \x20      solsign --addresses-csv [--no-prompt]\n\
+    \x20      solsign --list-instructions [--no-prompt]\n\
+    \x20      solsign --inspect [--no-prompt]\n\
+    \x20      solsign --dump-raw [--no-prompt]\n\
+    \x20      solsign --decode-json [--no-prompt]\n\
+    \x20      solsign --emit-message [--no-prompt]\n\
+    \x20      solsign --strip-signatures [--no-prompt] [--no-wrap]\n\
+    \x20      solsign --merge [--no-prompt] [--no-wrap] [--cli-json]\n\
+    \x20      solsign --encode-json [--no-wrap]\n\
+    \x20      solsign --build-transfer FROM TO LAMPORTS --blockhash BLOCKHASH\n\
+    \x20              [--no-wrap]\n\
+    \x20      solsign --self-test\n\n\
     \x20 solsign reads Solana transactions in Base64 encoded format from stdin,\n\
     \x20 displays them, signs them, writes signed transactions and signatures to\n\
-    \x20 stdout.\n\n\
+    \x20 stdout.  Base58 encoded transactions are also accepted, and one that is\n\
+    \x20 split over several lines must be followed by an empty line.  If input\n\
+    \x20 is a valid transaction in both encodings, solsign asks which was meant.\n\
+    \x20 A line holding only . after a transaction says that all of it has been\n\
+    \x20 entered, so that incomplete input is reported instead of waited on.  If\n\
+    \x20 input at a terminal stays incomplete for a few seconds, solsign asks\n\
+    \x20 whether to keep waiting for the rest of it or to clear it.\n\n\
     \x20 On start-up, solsign reads any private key files specified on the command\n\
-    \x20 line. It also prompts for mnemonic and passcode combinations from stdin.\n\
+    \x20 line; a directory given in place of a key file supplies every .json file\n\
+    \x20 within it as a key file.  A leading ~ and environment variables, written\n\
+    \x20 as $NAME or ${NAME}, are expanded in key file paths as a shell would.\n\
+    \x20 A key file is either the JSON array of key bytes written by solana-keygen,\n\
+    \x20 a JSON object with a \"seed_phrase\" and optionally a \"passphrase\" and\n\
+    \x20 a \"derivation_path\", from which the key is derived, or the Base58\n\
+    \x20 encoded 64 byte keypair that wallets export.\n\
+    \x20 It also prompts for mnemonic and passcode combinations from stdin.\n\
     \x20 Collectively these signing keys become available to the program to sign\n\
-    \x20 transactions.\n\n\
+    \x20 transactions.  A mnemonic which is not a valid BIP39 mnemonic is only\n\
+    \x20 used if the user confirms that it should be.\n\n\
+    \x20 --seed-hex supplies a 64 byte seed, as 128 hex digits, in place of a\n\
+    \x20 mnemonic and passcode.  The key is taken directly from the seed, or is\n\
+    \x20 derived from it with PATH, such as m/44'/501'/0'/0', if --derivation-path\n\
+    \x20 is given.  This is meant for testing; seeds given on the command line may\n\
+    \x20 be seen by other users of the computer.\n\n\
+    \x20 --mnemonic-file reads BIP39 mnemonics from a file, one per line, and\n\
+    \x20 --passphrase-file their passphrase, so that keys can be derived from\n\
+    \x20 mnemonics without prompting.  A single key is derived from each, with\n\
+    \x20 the path given by --derivation-path.  A mnemonic may instead be followed\n\
+    \x20 on its line by a tab and its own passphrase, and then by another tab and\n\
+    \x20 its own derivation path.\n\n\
+    \x20 --pubkey-prefix highlights the keys derived from a mnemonic whose Base58\n\
+    \x20 encoded public key starts with PREFIX, and if only one does, selects it\n\
+    \x20 without asking, so that a key known by the start of its public key can\n\
+    \x20 be found among them.\n\n\
     \x20 After reading in private keys, solsign asks the user to supply a challenge\n\
     \x20 password which will be used to ensure that the correct user is signing\n\
     \x20 subsequent transactions.  Entering a password is highly recommended as it\n\
     \x20 will protect the user in case an intruder gains access to the command\n\
-    \x20 line.\n\n\
+    \x20 line.  --no-challenge skips asking for a challenge password, while still\n\
+    \x20 prompting for keys.  --no-key-prompt does the opposite, using only keys\n\
+    \x20 from key files but still asking for a challenge password.  The password\n\
+    \x20 is read from the terminal, so transactions may then be piped to stdin.\n\
+    \x20 --password-fd reads the challenge password from the first line of file\n\
+    \x20 descriptor N instead, as for a password manager, without it appearing\n\
+    \x20 on the command line or in the environment.  It is only available on\n\
+    \x20 Unix.\n\n\
+    \x20 Passwords, passphrases and mnemonics are typed without being shown.  If\n\
+    \x20 the terminal can't be set to hide them, solsign exits, unless\n\
+    \x20 --insecure-echo is given, in which case it warns and then reads them\n\
+    \x20 from stdin with what is typed shown, where anyone nearby can see it.\n\n\
+    \x20 --keys-from-stdin reads keys from stdin without prompting, one per line,\n\
+    \x20 until an empty line, after which transactions follow.  Each key is\n\
+    \x20 either a Base58 encoded 64 byte keypair, or a BIP39 mnemonic with no\n\
+    \x20 passphrase, from which the key is taken as for --seed-hex.  This makes\n\
+    \x20 fully scripted sessions possible without driving the key prompts.\n\n\
+    \x20 --keyring keeps keys in the file at PATH, encrypted with a passphrase, so\n\
+    \x20 that they need not be supplied again in every session.  If PATH does not\n\
+    \x20 exist, the keys supplied in the session are written to it, encrypted\n\
+    \x20 with a new passphrase that is asked for; if it does exist, its\n\
+    \x20 passphrase is asked for and its keys are loaded along with any others.\n\
+    \x20 This is only available if solsign was built with the keyring feature.\n\n\
     \x20 solsign then enters a loop where it waits to read Base64 encoded\n\
     \x20 transactions from standard input. After each encoded transaction is read\n\
     \x20 in, if there was a challenge password set, solsign will require the user\n\
-    \x20 to supply that password before proceeding.  For any signatures not\n\
-    \x20 provided within the transaction, if the key required for that signature\n\
-    \x20 was provided to solsign, the transaction will be signed with that key.\n\n\
+    \x20 to supply that password before proceeding, unless none of the keys needed\n\
+    \x20 to sign the transaction were provided.  Entering :show at the challenge\n\
+    \x20 password prompt displays the transaction summary again.  For any\n\
+    \x20 signatures not provided within the transaction, if the key required for\n\
+    \x20 that signature was provided to solsign, the transaction will be signed\n\
+    \x20 with that key.  When input ends, the keys which were loaded but never\n\
+    \x20 used to sign a transaction are listed.\n\
+    \x20 --count prints how many signatures were added, how many were already\n\
+    \x20 present, and how many are still missing.\n\n\
+    \x20 --program-filter limits the instructions shown in full in the transaction\n\
+    \x20 summary to those of the Base58 encoded PROGRAM, and may be given more\n\
+    \x20 than once.  Instructions of other programs are shown on a single line.\n\n\
+    \x20 --sol shows the amounts of SOL transfers in SOL rather than lamports, and\n\
+    \x20 of token transfers that give the token's decimals in whole tokens rather\n\
+    \x20 than its smallest unit, with the exact amount following in parentheses.\n\n\
+    \x20 --explain adds to the transaction summary the reason that each signer's\n\
+    \x20 signature is required: whether it is the fee payer, and the instructions\n\
+    \x20 in which it is a signer.\n\n\
+    \x20 --signature attaches a Base58 encoded signature made elsewhere by PUBKEY\n\
+    \x20 to each transaction that PUBKEY must sign.  The signature is first\n\
+    \x20 verified against the transaction, and is not attached if it does not\n\
+    \x20 sign that transaction.\n\n\
+    \x20 --signer limits the loaded keys that sign to the key of the Base58\n\
+    \x20 encoded PUBKEY, and may be given more than once.  Other loaded\n\
+    \x20 keys are left unused, and their signatures are left missing, so that each\n\
+    \x20 party to a transaction signs only for itself even if holding other keys.\n\n\
+    \x20 If --allow-destination is given, solsign will not sign a transaction that\n\
+    \x20 gives SOL, tokens or control of them to any account other than those\n\
+    \x20 given.  That is the destination of a System Transfer, TransferWithSeed\n\
+    \x20 or WithdrawNonceAccount, the new account of a System CreateAccount or\n\
+    \x20 CreateAccountWithSeed, the destination of a Token Transfer,\n\
+    \x20 TransferChecked or CloseAccount, the delegate of a Token Approve or\n\
+    \x20 ApproveChecked, and the new authority of a Token SetAuthority, for both\n\
+    \x20 the Token and Token-2022 programs.  Likewise --max-lamports refuses\n\
+    \x20 transactions whose transfers, account creations and nonce withdrawals\n\
+    \x20 move more than N lamports in total, and --max-token-amount refuses those\n\
+    \x20 whose token transfers and approvals from any one account total more\n\
+    \x20 than N of the token's smallest unit.  When any of these options is\n\
+    \x20 given, a transaction with any other instruction of the System, Token or\n\
+    \x20 Token-2022 programs, apart from AdvanceNonceAccount, is refused as it\n\
+    \x20 cannot be checked.\n\n\
+    \x20 --max-signers refuses transactions that require more than N signatures,\n\
+    \x20 as each signature adds to the fee paid.  It defaults to 18, the most\n\
+    \x20 that any transaction can require.\n\n\
+    \x20 --policy-command runs COMMAND through the shell before each transaction is\n\
+    \x20 signed, with the transaction as JSON (as written by --decode-json) on its\n\
+    \x20 stdin.  The transaction is signed only if COMMAND exits successfully.\n\n\
+    \x20 A transaction with an empty (all zero) recent blockhash can never execute,\n\
+    \x20 so solsign will not sign it unless --allow-empty-blockhash is given.\n\n\
     \x20 After all possible signatures are applied, if the transaction is still not\n\
     \x20 completely signed, then the list of pubkeys which must still sign the\n\
     \x20 transaction is printed, along with the Base64 encoded version of the\n\
-    \x20 partially signed transaction is printed, ready for further signing.\n\n\
+    \x20 partially signed transaction is printed, ready for further signing.  With\n\
+    \x20 --no-prompt, a \"missing-signers: N\" line followed by one\n\
+    \x20 \"missing-signer: PUBKEY\" line per missing signer is also written to\n\
+    \x20 stderr.\n\n\
+    \x20 With --require-complete, a transaction that is still not completely\n\
+    \x20 signed is an error instead: the pubkeys which must still sign it are\n\
+    \x20 written to stderr, nothing is written for the transaction, and solsign\n\
+    \x20 exits with an error status.\n\n\
     \x20 If after signing, the transaction is completely signed, then the signature\n\
-    \x20 of the transaction is printed.\n".to_string()
+    \x20 of the transaction is printed, in the FORMAT given by --signature-format:\n\
+    \x20 base58 (the default), base64 or hex.  With --signature-only, that\n\
+    \x20 signature is all that is written to stdout other than prompts, on a line\n\
+    \x20 of its own; the summary of the transaction is written to stderr, and\n\
+    \x20 nothing is written to stdout for a transaction that is not completely\n\
+    \x20 signed, only the \"missing-signer\" lines to stderr.\n\n\
+    \x20 Base64 encoded transactions are printed wrapped and indented for reading;\n\
+    \x20 --no-wrap prints each on a single unindented line instead.  Wherever\n\
+    \x20 --no-wrap is accepted, --wrap-width N wraps at N columns rather than 72\n\
+    \x20 and --indent N indents by N spaces rather than 4; --indent 0 gives\n\
+    \x20 unindented lines for piping.  --cli-json prints them as the JSON used by\n\
+    \x20 the solana command line tools and web3.js instead.\n\n\
+    \x20 With --binary, transactions are read from stdin in their binary wire\n\
+    \x20 format, one directly after another, rather than encoded as text, and\n\
+    \x20 each is written to stdout in the same format once signed.  Keys are not\n\
+    \x20 prompted for, and everything other than the transactions is written to\n\
+    \x20 stderr, including the \"missing-signer\" lines described above.\n\n\
+    \x20 --length-prefixed is --binary, except that each transaction written to\n\
+    \x20 stdout is preceded by its length in bytes as a 4 byte little-endian\n\
+    \x20 unsigned integer, so that a stream of many signed transactions can be\n\
+    \x20 split apart without decoding them.  Transactions read from stdin are\n\
+    \x20 not length prefixed.\n\n\
+    \x20 With --concatenated, stdin holds Base64 encoded transactions one directly\n\
+    \x20 after another with nothing between them, in any number of lines, as some\n\
+    \x20 programs write them.  All of stdin is read and decoded, and then each\n\
+    \x20 transaction is signed in turn.  As with --binary, keys are not prompted\n\
+    \x20 for.\n\n\
+    \x20 A transaction with no instructions does nothing, so solsign warns about\n\
+    \x20 it and only signs it if the user confirms that it should; it is never\n\
+    \x20 signed with --no-prompt, --binary or --concatenated.\n\n\
+    \x20 --assume-yes, or -y, answers yes in advance to every question asking\n\
+    \x20 whether to go ahead despite a warning.  A mnemonic that is not valid\n\
+    \x20 BIP39 is used, whether entered at the prompt, read by --keys-from-stdin\n\
+    \x20 or given in --mnemonic-file, though not as the seed_phrase of a key file.\n\
+    \x20 A transaction with no instructions is signed, even with --no-prompt,\n\
+    \x20 --binary or --concatenated.  The warnings are still printed.  It does\n\
+    \x20 not override refusals that have options of their own, such as\n\
+    \x20 --allow-empty-blockhash, nor --allow-destination, the --max- limits or\n\
+    \x20 --policy-command.\n\n\
+    \x20 A transaction that is identical, apart from its signatures, to one\n\
+    \x20 already read during the session is warned about, as it may be a copy\n\
+    \x20 given twice by mistake.  It is still signed as usual.\n\n\
+    \x20 Warnings are written to stderr, each starting with \"WARNING[CODE]:\" so\n\
+    \x20 that scripts can look for particular warnings.  CODE is one of\n\
+    \x20 empty-blockhash, no-instructions, duplicate-transaction, oversize,\n\
+    \x20 duplicate-account, invalid-signature, unused-keys, invalid-mnemonic,\n\
+    \x20 no-prefix-match and insecure-echo.\n\n\
+    \x20 Instead of a transaction, the command :remove may be entered to select one\n\
+    \x20 of the provided keys and remove it so that it can no longer be used.\n\n\
+    \x20 With --sign-message, solsign instead reads messages from stdin, one per\n\
+    \x20 line, and signs each with a key selected from those provided, printing\n\
+    \x20 the resulting signature.  Each is signed as a Solana off-chain message,\n\
+    \x20 as solana sign-offchain-message does, whose header no transaction can\n\
+    \x20 start with, so that a message can never be crafted to have its signature\n\
+    \x20 authorize a transaction.\n\n\
+    \x20 With --ata, solsign does not sign anything; it prints the address of the\n\
+    \x20 associated token account for MINT of each key provided, under both the\n\
+    \x20 token program and the Token-2022 program.\n\n\
+    \x20 With --show-pubkeys, solsign does not sign anything; it prints the Base58\n\
+    \x20 encoded public key of each key provided, one per line.  That is every\n\
+    \x20 key loaded from key files, a keyring, --mnemonic-file, --seed-hex,\n\
+    \x20 --keys-from-stdin or the mnemonic prompt.\n\n\
+    \x20 With --generate, solsign does not sign anything; it creates a new random\n\
+    \x20 key, writes it to a new key file at PATH in the format of solana-keygen,\n\
+    \x20 and prints its Base58 encoded public key.  An existing file is never\n\
+    \x20 overwritten.\n\n\
+    \x20 Key files written by --generate and keyrings written by --keyring are\n\
+    \x20 created readable only by their owner.  solsign can only ensure that on\n\
+    \x20 Unix, so elsewhere, including on Windows, it refuses to write them.\n\n\
+    \x20 With --addresses, solsign does not sign anything; it only lists the\n\
+    \x20 addresses of each transaction read, one per line, with the index of the\n\
+    \x20 address and whether it is a signer and whether it is writable.\n\
+    \x20 --addresses-csv lists the same as CSV, for importing into a spreadsheet,\n\
+    \x20 with a header row followed by a row per address giving the Base58\n\
+    \x20 address, whether it is a signer, whether it is writable, and its index.\n\n\
+    \x20 With --list-instructions, solsign does not sign anything; it only lists\n\
+    \x20 the instructions of each transaction read, one per line, with the index\n\
+    \x20 of the instruction, its program id, and how many accounts and bytes of\n\
+    \x20 data it has.\n\n\
+    \x20 With --inspect, solsign reads the Base64 encoded data of address lookup\n\
+    \x20 table accounts instead of transactions, each followed by an empty line,\n\
+    \x20 and lists the addresses that each holds, with the index by which a v0\n\
+    \x20 transaction refers to each.  Such data may be fetched with \"solana\n\
+    \x20 account --output json\".\n\n\
+    \x20 With --dump-raw, solsign does not sign anything; it reads Base64 encoded\n\
+    \x20 transactions, each followed by an empty line, and prints a hex dump of\n\
+    \x20 the bytes of each with the offset and the field of the transaction that\n\
+    \x20 each belongs to.  A transaction that fails to decode is dumped as far as\n\
+    \x20 it was read, followed by the bytes that were not read, which helps in\n\
+    \x20 finding out why it is invalid.\n\n\
+    \x20 With --decode-json, solsign does not sign anything; it writes each\n\
+    \x20 transaction read as JSON, with Base58 encoded addresses, signatures and\n\
+    \x20 recent blockhash, and instructions giving their program id, account\n\
+    \x20 indices and Base64 encoded data.\n\n\
+    \x20 With --encode-json, solsign reads transactions described in the JSON\n\
+    \x20 written by --decode-json from stdin, and writes the Base64 encoding of\n\
+    \x20 each, ready to be signed.\n\n\
+    \x20 With --build-transfer, solsign does not sign anything; it writes the\n\
+    \x20 Base64 encoding of a new transaction that transfers LAMPORTS from the\n\
+    \x20 Base58 encoded pubkey FROM, which pays its fee, to the Base58 encoded\n\
+    \x20 address TO, ready to be signed.  The Base58 encoded recent blockhash\n\
+    \x20 BLOCKHASH, such as is printed by \"solana block\", must be given with\n\
+    \x20 --blockhash.\n\n\
+    \x20 With --emit-message, solsign does not sign anything; it writes the\n\
+    \x20 message of each transaction read, which is the exact bytes that each\n\
+    \x20 signer signs, Base64 encoded on a single line.  This is for signing with\n\
+    \x20 an external device, whose signatures may then be given to --signature.\n\n\
+    \x20 With --strip-signatures, solsign does not sign anything; it removes every\n\
+    \x20 signature from each transaction read and writes the unsigned transaction,\n\
+    \x20 ready to be signed anew after its recent blockhash or instructions are\n\
+    \x20 changed.\n\n\
+    \x20 With --merge, solsign reads pairs of copies of a transaction, each signed\n\
+    \x20 by different signers, and writes a single transaction carrying every\n\
+    \x20 valid signature from both.  Copies whose messages differ in any way are\n\
+    \x20 not merged.\n\n\
+    \x20 With --self-test, solsign builds, encodes, decodes and signs transactions\n\
+    \x20 of its own with keys of its own, printing PASS or FAIL for each check,\n\
+    \x20 and exits with an error status if any failed.  This is a quick check\n\
+    \x20 that solsign works correctly on the computer it is run on.\n\n\
+    \x20 Ending input (i.e. Ctrl-D) part way through a transaction signs what was\n\
+    \x20 read, if it is a complete transaction, and then ends the session.  At the\n\
+    \x20 challenge password prompt it abandons signing of just that transaction.\n\
+    \x20 At any other prompt it ends the session.\n\n\
+    \x20 Output written to a terminal is colored; set NO_COLOR to disable this.\n".to_string()
 }
 
 // This comes from solana validator code base, which requires all transactions to fit inside an IPV4 UDP packet
@@ -70,13 +326,146 @@ pub const MAXIMUM_INSTRUCTION_DATA_COUNT : u16 = 1192;
 // (1232 - (1 + 4 + 32) - 2) / 3
 pub const MAXIMUM_INSTRUCTIONS_COUNT : u16 = 397;
 
-#[derive(Clone, PartialEq)]
+// Options parsed from the command line
+#[derive(Default)]
+struct Options
+{
+    pub no_prompt : bool,
+
+    pub sign_message : bool,
+
+    pub addresses : bool,
+
+    // List the addresses of each transaction as CSV
+    pub addresses_csv : bool,
+
+    pub inspect : bool,
+
+    pub dump_raw : bool,
+
+    pub list_instructions : bool,
+
+    pub self_test : bool,
+
+    pub allow_empty_blockhash : bool,
+
+    pub no_challenge : bool,
+
+    // Answer yes to every question asking whether to go ahead despite a warning
+    pub assume_yes : bool,
+
+    pub no_key_prompt : bool,
+
+    pub count : bool,
+
+    // Show transfer amounts in SOL and whole tokens
+    pub sol : bool,
+
+    // If not empty, only the loaded keys of these pubkeys sign
+    pub signers : Vec<Pubkey>,
+
+    // Show why each signer's signature is required
+    pub explain : bool,
+
+    // Exit with an error rather than write a transaction that is not completely signed
+    pub require_complete : bool,
+
+    pub decode_json : bool,
+
+    pub encode_json : bool,
+
+    pub emit_message : bool,
+
+    pub merge : bool,
+
+    pub binary : bool,
+
+    // With binary, precede each transaction written to stdout by its length
+    pub length_prefixed : bool,
+
+    // Read Base64 encoded transactions that follow one another with no separator
+    pub concatenated : bool,
+
+    pub keys_from_stdin : bool,
+
+    pub no_wrap : bool,
+
+    // Columns at which Base64 output is wrapped, and spaces by which it is indented, if not the defaults
+    pub wrap_width : Option<usize>,
+
+    pub indent : Option<usize>,
+
+    pub cli_json : bool,
+
+    pub strip_signatures : bool,
+
+    pub show_pubkeys : bool,
+
+    pub signature_format : SignatureFormat,
+
+    pub signature_only : bool,
+
+    // Signatures produced elsewhere, to be attached to transactions that they sign
+    pub signatures : Vec<(Pubkey, ed25519_dalek::Signature)>,
+
+    // Shell command which must approve each transaction before it is signed
+    pub policy_command : Option<String>,
+
+    // If not empty, the only accounts that SOL and tokens may be transferred to
+    pub allowed_destinations : Vec<Address>,
+
+    // The most that a transaction may transfer, in lamports and in a token's smallest unit from any one account
+    pub max_lamports : Option<u64>,
+
+    pub max_token_amount : Option<u64>,
+
+    // The most signatures that a transaction may require; None allows as many as any transaction can have
+    pub max_signers : Option<u64>,
+
+    // File descriptor from which to read the challenge password, rather than prompting for it
+    pub password_fd : Option<u32>,
+
+    // If not empty, the only programs whose instructions are shown in full
+    pub program_filter : Vec<Address>,
+
+    pub key_files : Vec<String>,
+
+    // Start of the public key of the key to select from those derived from a mnemonic
+    pub pubkey_prefix : Option<String>,
+
+    // Raw seeds given in place of a mnemonic and passphrase, and the derivation path to derive their keys with, if any
+    pub seeds : Vec<Vec<u8>>,
+
+    pub derivation_path : Option<derivation_path::DerivationPath>,
+
+    // Files holding a mnemonic and its passphrase, to derive a key from without prompting
+    pub mnemonic_file : Option<String>,
+
+    pub passphrase_file : Option<String>,
+
+    // File of encrypted keys to load keys from, or to write the session's keys to if it doesn't exist yet
+    pub keyring : Option<String>,
+
+    // Mint to print the associated token accounts of the loaded keys for, instead of signing anything
+    pub ata_mint : Option<Address>,
+
+    // Path of a new key file to generate a key into, instead of signing anything
+    pub generate : Option<String>,
+
+    // The payer, destination and lamports of a transfer transaction to build, instead of signing anything
+    pub build_transfer : Option<(Pubkey, Address, u64)>,
+
+    // Recent blockhash of the transaction built by --build-transfer
+    pub blockhash : Option<Sha256Digest>
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 struct Pubkey(pub [u8; 32]);
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Eq, Hash)]
 struct Sha256Digest(pub [u8; 32]);
 
-#[derive(Clone, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 struct Address(pub [u8; 32]);
 
 #[derive(Clone)]
@@ -87,6 +476,7 @@ struct PubkeyWithSignature
     pub signature : Option<ed25519_dalek::Signature>
 }
 
+#[derive(Clone)]
 struct Transaction
 {
     pub signed_read_write_addresses : Vec<PubkeyWithSignature>,
@@ -102,6 +492,165 @@ struct Transaction
     pub instructions : Vec<Instruction>
 }
 
+// Errors which can occur while decoding or encoding a transaction
+#[derive(Debug)]
+enum TxError
+{
+    // The input ended before a complete transaction was read, so more input may yet complete it
+    Truncated,
+
+    Io(std::io::Error),
+
+    // A signature that is not a valid ed25519 signature at all, along with the signer that it is for
+    InvalidSignature
+    {
+        signer : Pubkey,
+        error : ed25519_dalek::SignatureError
+    },
+
+    // A compact-u16 that is longer than needed for its value, or too large; holds the bytes read
+    InvalidCompactU16([u8; 3]),
+
+    TooManySignatures
+    {
+        maximum : usize,
+        actual : usize
+    },
+
+    TooManyReadOnlySigners
+    {
+        maximum : usize,
+        actual : usize
+    },
+
+    MissingFeePayer,
+
+    // A signer appears more than once among the read-write signers, or more than once among the read-only signers
+    DuplicateSigner(Pubkey),
+
+    TooManyAddresses
+    {
+        maximum : usize,
+        actual : usize
+    },
+
+    TooFewAddresses
+    {
+        minimum : usize,
+        actual : usize
+    },
+
+    TooManyInstructions
+    {
+        maximum : usize,
+        actual : usize
+    },
+
+    TooManyInstructionAddresses
+    {
+        instruction : usize,
+        maximum : usize,
+        actual : usize
+    },
+
+    TooMuchInstructionData
+    {
+        instruction : usize,
+        maximum : usize,
+        actual : usize
+    },
+
+    InvalidProgramIndex
+    {
+        instruction : usize,
+        index : u8
+    },
+
+    InvalidAddressIndex
+    {
+        instruction : usize,
+        index : u8
+    },
+
+    // An instruction refers to an address that is not one of the transaction's addresses
+    AddressNotFound(Address),
+
+    // Decoding failed once offset bytes had been read, which is just past the part of the transaction in error
+    AtOffset
+    {
+        offset : usize,
+        error : Box<TxError>
+    }
+}
+
+// Counts the bytes read through it, so that decoding errors can say where in the transaction they occurred
+struct CountingReader<'a>
+{
+    inner : &'a mut dyn std::io::Read,
+
+    count : usize,
+
+    // If recording, the name of each field of the transaction and the offset at which it starts
+    fields : Option<Vec<(String, usize)>>
+}
+
+// Instructions which solsign knows how to describe
+enum KnownInstruction
+{
+    SetComputeUnitLimit(u32),
+
+    // In micro-lamports per compute unit
+    SetComputeUnitPrice(u64),
+
+    // Lamports transferred and the account they are transferred to
+    Transfer(u64, Address),
+
+    // Lamports given to the new account, which is created either directly or with a seed
+    CreateAccount(u64, Address),
+
+    // The nonce account
+    AdvanceNonceAccount(Address),
+
+    // Lamports withdrawn from a nonce account and the account they are withdrawn to
+    WithdrawNonceAccount(u64, Address),
+
+    // Amount in the token's smallest unit and the token account it is transferred to
+    TokenTransfer(u64, Address),
+
+    // Amount in the token's smallest unit that the delegate may transfer
+    TokenApprove(u64, Address),
+
+    // The token account being closed and the account its lamports go to
+    TokenCloseAccount(Address, Address),
+
+    // The new authority, or None if the authority is being removed
+    TokenSetAuthority(Option<Address>)
+}
+
+// The encoding in which the signature of a completely signed transaction is printed
+#[derive(Clone, Copy, Default)]
+enum SignatureFormat
+{
+    #[default]
+    Base58,
+
+    Base64,
+
+    Hex
+}
+
+// What was read in at the transaction prompt
+enum Input
+{
+    Transaction(Transaction),
+
+    Command(String),
+
+    // Input ended (i.e. Ctrl-D), so the session is over
+    End
+}
+
+#[derive(Clone)]
 struct Instruction
 {
     pub program_address : Address,
@@ -112,10 +661,108 @@ struct Instruction
     pub data : Vec<u8>
 }
 
+// An account of a transaction, along with the role that the transaction gives it
+#[derive(Clone, Debug, PartialEq)]
+struct AccountMeta
+{
+    pub address : Address,
+
+    pub is_signer : bool,
+
+    pub is_writable : bool
+}
+
+// The state of an address lookup table account, from which v0 transactions can take addresses by index
+struct LookupTable
+{
+    // u64::MAX if the table has not been deactivated
+    pub deactivation_slot : u64,
+
+    pub last_extended_slot : u64,
+
+    // None if the table has been frozen
+    pub authority : Option<Pubkey>,
+
+    pub addresses : Vec<Address>
+}
+
+// The account data of an address lookup table is a fixed size header followed by its addresses
+const LOOKUP_TABLE_HEADER_BYTES : usize = 56;
+
+// The most addresses that a lookup table may hold, as they are referred to by a u8 index
+const MAXIMUM_LOOKUP_TABLE_ADDRESSES_COUNT : usize = 256;
+
 const EMPTY_RECENT_BLOCKHASH : Sha256Digest = Sha256Digest([0_u8; 32]);
 
 const EMPTY_SIGNATURE_BYTES : [u8; 64] = [0_u8; 64];
 
+const BASE58_ALPHABET : &str = "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+const SYSTEM_PROGRAM_ADDRESS : Address = Address([0_u8; 32]);
+
+// The fee charged by the network for each signature of a transaction, before any prioritization fee
+const LAMPORTS_PER_SIGNATURE : u64 = 5000;
+
+// Indices of instructions of the system program
+const SYSTEM_CREATE_ACCOUNT : u32 = 0;
+const SYSTEM_TRANSFER : u32 = 2;
+const SYSTEM_CREATE_ACCOUNT_WITH_SEED : u32 = 3;
+const SYSTEM_ADVANCE_NONCE_ACCOUNT : u32 = 4;
+const SYSTEM_WITHDRAW_NONCE_ACCOUNT : u32 = 5;
+const SYSTEM_TRANSFER_WITH_SEED : u32 = 11;
+
+// TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA
+const TOKEN_PROGRAM_ADDRESS : Address = Address([
+    6, 221, 246, 225, 215, 101, 161, 147, 217, 203, 225, 70, 206, 235, 121, 172, 28, 180, 133, 237, 95, 91, 55, 145,
+    58, 140, 245, 133, 126, 255, 0, 169
+]);
+
+// TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb
+const TOKEN_2022_PROGRAM_ADDRESS : Address = Address([
+    6, 221, 246, 225, 238, 117, 143, 222, 24, 66, 93, 188, 228, 108, 205, 218, 182, 26, 252, 77, 131, 185, 13, 39, 254,
+    189, 249, 40, 216, 161, 139, 252
+]);
+
+// ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL
+const ASSOCIATED_TOKEN_PROGRAM_ADDRESS : Address = Address([
+    140, 151, 37, 143, 78, 36, 137, 241, 187, 61, 16, 41, 20, 142, 13, 131, 11, 90, 19, 153, 218, 255, 16, 132, 4, 142,
+    123, 216, 219, 233, 248, 89
+]);
+
+// Instruction tags of the token programs, which Token-2022 shares with the original token program
+const TOKEN_TRANSFER : u8 = 3;
+const TOKEN_APPROVE : u8 = 4;
+const TOKEN_SET_AUTHORITY : u8 = 6;
+const TOKEN_CLOSE_ACCOUNT : u8 = 9;
+const TOKEN_TRANSFER_CHECKED : u8 = 12;
+const TOKEN_APPROVE_CHECKED : u8 = 13;
+
+// A SOL is 10^9 lamports
+const SOL_DECIMALS : u8 = 9;
+
+// ComputeBudget111111111111111111111111111111
+const COMPUTE_BUDGET_PROGRAM_ADDRESS : Address = Address([
+    3, 6, 70, 111, 229, 33, 23, 50, 255, 236, 173, 186, 114, 195, 155, 231, 188, 140, 229, 187, 197, 247, 18, 107, 44,
+    67, 155, 58, 64, 0, 0, 0
+]);
+
+// Instruction tags of the compute budget program
+const COMPUTE_BUDGET_SET_COMPUTE_UNIT_LIMIT : u8 = 2;
+const COMPUTE_BUDGET_SET_COMPUTE_UNIT_PRICE : u8 = 3;
+
+// A transaction that does not set a compute unit limit gets this many units per instruction, up to the maximum
+const DEFAULT_INSTRUCTION_COMPUTE_UNIT_LIMIT : u64 = 200_000;
+const MAXIMUM_COMPUTE_UNIT_LIMIT : u64 = 1_400_000;
+
+// Compute unit prices are in micro-lamports
+const MICRO_LAMPORTS_PER_LAMPORT : u128 = 1_000_000;
+
+// ANSI escape sequences used to color output
+const COLOR_RED : &str = "\x1b[31m";
+const COLOR_GREEN : &str = "\x1b[32m";
+const COLOR_YELLOW : &str = "\x1b[33m";
+const COLOR_RESET : &str = "\x1b[0m";
+
 // To be implemented: read a character in terminal raw mode, i.e. the moment that the user types a character,
 // return the typed character.  This prevents having to press return after entering a transaction, or after
 // entering a command key.
@@ -171,7 +818,7 @@ fn keypair_from_seed_and_derivation_path(
     derivation_path : derivation_path::DerivationPath
 ) -> Result<ed25519_dalek::Keypair, String>
 {
-    bip32_derived_keypair(seed, derivation_path).map_err(|err| err.to_string().into())
+    bip32_derived_keypair(seed, derivation_path).map_err(|err| err.to_string())
 }
 
 fn bip32_derived_keypair(
@@ -201,879 +848,5060 @@ fn generate_seed_from_seed_phrase_and_passphrase(
     seed
 }
 
-fn print_base64(bytes : &[u8])
+fn sha256(parts : &[&[u8]]) -> Sha256Digest
 {
-    let b = base64::encode(&bytes);
-    for idx in (0..b.len()).step_by(72) {
-        let end = std::cmp::min(idx + 72, b.len());
-        println!("    {}", &b[idx..end]);
-    }
+    use sha2::Digest;
+
+    let mut hasher = sha2::Sha256::new();
+    parts.iter().for_each(|part| hasher.update(part));
+    Sha256Digest(hasher.finalize().into())
 }
 
-impl Transaction
+// Finds the program derived address of the seeds, which is the first hash of the seeds, a bump seed counting down
+// from 255, and the program address, that is not a valid ed25519 public key, so that no private key can exist for it
+fn find_program_address(
+    seeds : &[&[u8]],
+    program_address : &Address
+) -> Option<(Address, u8)>
 {
-    pub fn decode(r : &mut dyn std::io::Read) -> Result<Self, Option<String>>
-    {
-        let signatures_count = Self::decode_compact_u16(r)?;
-
-        // Can't provide more signatures than allowed
-        if signatures_count > (MAXIMUM_ED25519_SIGNATURES_COUNT as u16) {
-            return Err(Some(format!(
-                "Too many signatures in transaction: expected at most {}, got {}",
-                MAXIMUM_ED25519_SIGNATURES_COUNT, signatures_count
-            )));
+    (0..=u8::MAX).rev().find_map(|bump| {
+        let mut parts = seeds.to_vec();
+        let bump_seed = [bump];
+        parts.extend_from_slice(&[&bump_seed, &program_address.0, b"ProgramDerivedAddress"]);
+        let digest = sha256(&parts);
+        // ed25519_dalek only accepts bytes which are a point on the curve as a public key
+        if ed25519_dalek::PublicKey::from_bytes(&digest.0).is_err() {
+            Some((Address(digest.0), bump))
+        }
+        else {
+            None
         }
+    })
+}
 
-        let mut signatures = Vec::<Option<ed25519_dalek::Signature>>::new();
+fn associated_token_address(
+    wallet : &Pubkey,
+    token_program_address : &Address,
+    mint : &Address
+) -> Option<Address>
+{
+    find_program_address(&[&wallet.0, &token_program_address.0, &mint.0], &ASSOCIATED_TOKEN_PROGRAM_ADDRESS)
+        .map(|(address, _)| address)
+}
 
-        let mut buf = [0_u8; 64];
+// Prints the associated token account of each key, for both the original token program and Token-2022, since the
+// mint could belong to either
+fn print_associated_token_addresses(
+    keys_in_order : &[Pubkey],
+    mint : &Address
+)
+{
+    println!("  Associated token accounts for mint {}:", mint);
 
-        for _ in 0..signatures_count {
-            Self::read(r, &mut buf)?;
-            signatures.push(if buf == EMPTY_SIGNATURE_BYTES {
-                None
+    for key in keys_in_order {
+        println!("\n    Wallet     : {}", key);
+        for (label, token_program_address) in
+            [("Token      : ", &TOKEN_PROGRAM_ADDRESS), ("Token-2022 : ", &TOKEN_2022_PROGRAM_ADDRESS)]
+        {
+            match associated_token_address(key, token_program_address, mint) {
+                Some(address) => println!("    {}{}", label, address),
+                None => println!("    {}None", label)
             }
-            else {
-                Some(ed25519_dalek::Signature::from_bytes(&buf).map_err(|e| format!("{}", e))?)
-            });
         }
+    }
 
-        Self::read(r, &mut buf[0..3])?;
+    println!();
+}
 
-        let total_signed_address_count = buf[0] as u16;
+// Color is only used when writing to a terminal, and never when the NO_COLOR environment variable is set
+fn use_color(is_terminal : bool) -> bool
+{
+    is_terminal && std::env::var_os("NO_COLOR").is_none()
+}
 
-        if total_signed_address_count > (MAXIMUM_ADDRESSES_COUNT as u16) {
-            return Err(Some(format!(
-                "Too many signatures supplied: expected at most {}, got {}",
-                total_signed_address_count, MAXIMUM_ADDRESSES_COUNT
-            )));
-        }
+// Returns text wrapped in the given color, if stdout should be colored
+fn color_out(
+    text : &str,
+    color : &str
+) -> String
+{
+    if use_color(std::io::stdout().is_terminal()) {
+        format!("{}{}{}", color, text, COLOR_RESET)
+    }
+    else {
+        text.to_string()
+    }
+}
 
-        // Our encoder always produces all signatures, but uses all zero signatures for those signatures which were
-        // not provided.  Other implementations may instead produce a short signatures list, which can only be
-        // signatures in order, with unsupplied signatures being zero.
-        if signatures_count > total_signed_address_count {
-            return Err(Some(format!(
-                "Too many signatures supplied: expected at most {}, got {}",
-                total_signed_address_count, signatures_count
-            )));
-        }
+// Writes a warning to stderr.  Each kind of warning has a short code that never changes, given as WARNING[code], so
+// that scripts can look for the warnings that matter to them without depending on the wording of the message.
+fn print_warning(
+    code : &str,
+    message : &str
+)
+{
+    eprintln!("\n  {}", color_err(&format!("WARNING[{}]: {}", code, message), COLOR_YELLOW));
+}
 
-        let signed_read_only_address_count = buf[1] as u16;
+// Returns text wrapped in the given color, if stderr should be colored
+fn color_err(
+    text : &str,
+    color : &str
+) -> String
+{
+    if use_color(std::io::stderr().is_terminal()) {
+        format!("{}{}{}", color, text, COLOR_RESET)
+    }
+    else {
+        text.to_string()
+    }
+}
 
-        if signed_read_only_address_count > total_signed_address_count {
-            return Err(Some(format!(
-                "Too many signed read only addresses: expected at most {}, got {}",
-                total_signed_address_count, signed_read_only_address_count
-            )));
-        }
+// Reads a keypair from a solana command line compatible json file, exiting if the file can't be read or is invalid
+fn read_key_file(path : &std::path::Path) -> ed25519_dalek::Keypair
+{
+    let key_file = path.display();
 
-        let signed_read_write_address_count = total_signed_address_count - signed_read_only_address_count;
+    let contents : String = std::fs::read_to_string(path).unwrap_or_else(|e| {
+        eprintln!("\n{}\n", color_err(&format!("ERROR: Failed to read key file {}: {}", key_file, e), COLOR_RED));
+        std::process::exit(-1);
+    });
 
-        if signed_read_write_address_count == 0 {
-            return Err(Some("Minimum signed address count of 1 required for fee payer".to_string()));
-        }
+    parse_key_file(&contents).unwrap_or_else(|e| {
+        eprintln!("\n{}\n", color_err(&format!("ERROR: Invalid key file {}: {}", key_file, e), COLOR_RED));
+        std::process::exit(-1);
+    })
+}
 
-        let unsigned_read_only_address_count = buf[2] as u16;
+// Parses the contents of a solana command line compatible json key file, which is a JSON array of the 64 bytes of a
+// keypair, a paper wallet JSON object giving a seed phrase, or the Base58 encoded keypair that wallets export.  Files
+// written on Windows may have CRLF line endings and start with a byte order mark, neither of which is part of the key.
+fn parse_key_file(contents : &str) -> Result<ed25519_dalek::Keypair, String>
+{
+    let trimmed = contents.trim_matches(is_ignored_in_input);
 
-        let minimum_address_count = total_signed_address_count + unsigned_read_only_address_count;
+    if trimmed.starts_with('{') {
+        return parse_seed_phrase_key_file(trimmed);
+    }
 
-        let actual_address_count = Self::decode_compact_u16(r)?;
+    if !trimmed.is_empty() && trimmed.chars().all(|c| BASE58_ALPHABET.contains(c)) {
+        let bytes = zeroize::Zeroizing::new(bs58::decode(trimmed).into_vec().map_err(|e| e.to_string())?);
+        return ed25519_dalek::Keypair::from_bytes(&bytes).map_err(|e| e.to_string());
+    }
 
-        if actual_address_count < minimum_address_count {
-            return Err(Some(format!(
-                "Too few addresses in header; {} supplied but at least {} required",
-                actual_address_count, minimum_address_count
-            )));
-        }
+    // Anything but the numbers of a JSON array would otherwise only show up as a confusing failure to parse a number
+    if let Some((position, c)) =
+        contents.char_indices().find(|(_, c)| !c.is_ascii_digit() && !is_ignored_in_input(*c) && !"[],".contains(*c))
+    {
+        return Err(format!("unexpected {:?} at byte {}; expected a JSON array of numbers", c, position));
+    }
 
-        let unsigned_read_write_address_count = actual_address_count - minimum_address_count;
+    // Strip whitespace and [ and ], split by , and then parse bytes
+    let private_key_bytes = contents
+        .chars()
+        .filter(|c| !is_ignored_in_input(*c) && (*c != '[') && (*c != ']'))
+        .collect::<String>()
+        .split(",")
+        .map(|s| s.parse::<u8>().map_err(|e| format!("invalid value {}: {}", s, e)))
+        .collect::<Result<Vec<u8>, String>>()?;
 
-        let mut ret = Transaction {
-            signed_read_write_addresses : vec![],
-            signed_read_only_addresses : vec![],
-            unsigned_read_write_addresses : vec![],
-            unsigned_read_only_addresses : vec![],
-            recent_blockhash : None,
-            instructions : vec![]
-        };
+    ed25519_dalek::Keypair::from_bytes(private_key_bytes.as_slice()).map_err(|e| e.to_string())
+}
 
-        let mut signatures_iter = signatures.into_iter();
+// Parses a key file holding a JSON object with the "seed_phrase" of a BIP39 mnemonic, as exported by some wallets,
+// along with its optional "passphrase" and "derivation_path".  Without a derivation path, the key is taken directly
+// from the seed, as solana-keygen does.
+fn parse_seed_phrase_key_file(contents : &str) -> Result<ed25519_dalek::Keypair, String>
+{
+    let value : serde_json::Value = serde_json::from_str(contents).map_err(|e| e.to_string())?;
 
-        for _ in 0..signed_read_write_address_count {
-            ret.signed_read_write_addresses.push(Self::decode_signature_from_header(&mut signatures_iter, r)?);
-        }
+    let field = |name : &str| match value.get(name) {
+        None | Some(serde_json::Value::Null) => Ok(None),
+        Some(serde_json::Value::String(s)) => Ok(Some(s.as_str())),
+        Some(_) => Err(format!("\"{}\" is not a string", name))
+    };
 
-        for _ in 0..signed_read_only_address_count {
-            ret.signed_read_only_addresses.push(Self::decode_signature_from_header(&mut signatures_iter, r)?);
-        }
+    let seed_phrase = field("seed_phrase")?.ok_or("expected a JSON array of numbers or a \"seed_phrase\"")?;
 
-        for _ in 0..unsigned_read_write_address_count {
-            ret.unsigned_read_write_addresses.push(Self::decode_address(r)?);
-        }
+    validate_mnemonic(seed_phrase)?;
 
-        for _ in 0..unsigned_read_only_address_count {
-            ret.unsigned_read_only_addresses.push(Self::decode_address(r)?);
-        }
+    let seed = generate_seed_from_seed_phrase_and_passphrase(seed_phrase, field("passphrase")?.unwrap_or(""));
 
-        ret.recent_blockhash = Self::decode_recent_blockhash(r)?;
+    match field("derivation_path")? {
+        Some(path) => keypair_from_seed_and_derivation_path(
+            &seed,
+            path.parse().map_err(|e| format!("invalid derivation path {}: {}", path, e))?
+        ),
+        None => keypair_from_seed(&seed)
+    }
+}
 
-        let instruction_count = Self::decode_compact_u16(r)?;
+// Whitespace, including the \r of CRLF line endings and Unicode whitespace, and the invisible characters that editors
+// and copying from web pages can add, none of which can be part of a key file or an encoded transaction
+fn is_ignored_in_input(c : char) -> bool
+{
+    c.is_whitespace() || (c == '\u{feff}') || (c == '\u{200b}')
+}
 
-        for i in 0..instruction_count {
-            let i = i as usize;
-            Self::read(r, &mut buf[0..1])?;
+// Reads keys from stdin, one per line, until an empty line, for --keys-from-stdin.  Each is either a Base58 encoded
+// keypair, or a mnemonic, which is used without a passphrase and with derivation_path if given.  Exits if a key is
+// invalid, as there is no one to ask what was meant, unless it is a mnemonic that isn't valid BIP39 and assume_yes is
+// set.
+fn read_keys_from_stdin(
+    derivation_path : Option<&derivation_path::DerivationPath>,
+    assume_yes : bool
+) -> Vec<ed25519_dalek::Keypair>
+{
+    let mut keypairs = vec![];
 
-            let program_address = ret
-                .find_address_at_index(buf[0])
-                .ok_or(format!("Invalid program id index {} for instruction {}", buf[0], i))?;
+    for line_number in 1.. {
+        let line = read_stdin_line().unwrap_or_default();
 
-            let addresses_count = Self::decode_compact_u16(r)?;
+        let line = line.trim();
 
-            if addresses_count > MAXIMUM_INSTRUCTION_ADDRESS_INDEX_COUNT {
-                return Err(Some(format!(
-                    "Too many addresses in instruction {}: expected at most {} got {}",
-                    i, MAXIMUM_INSTRUCTION_ADDRESS_INDEX_COUNT, addresses_count
-                )));
-            }
+        if line.is_empty() {
+            break;
+        }
 
-            let mut addresses = Vec::<(Address, bool, bool)>::new();
+        // A mnemonic is the only form of key with more than one word
+        let keypair = if line.contains(char::is_whitespace) {
+            validate_mnemonic_unless_assumed(line, assume_yes, &format!("line {} of stdin", line_number)).and_then(
+                |_| {
+                    let seed = generate_seed_from_seed_phrase_and_passphrase(line, "");
+                    match derivation_path {
+                        Some(derivation_path) => keypair_from_seed_and_derivation_path(&seed, derivation_path.clone()),
+                        None => keypair_from_seed(&seed)
+                    }
+                }
+            )
+        }
+        else {
+            bs58::decode(line)
+                .into_vec()
+                .map_err(|e| e.to_string())
+                .and_then(|bytes| ed25519_dalek::Keypair::from_bytes(&bytes).map_err(|e| e.to_string()))
+        };
 
-            for _ in 0..addresses_count {
-                Self::read(r, &mut buf[0..1])?;
-                addresses.push(
-                    ret.find_address_at_index(buf[0])
-                        .ok_or(format!("Invalid address index {} referenced from instruction {}", buf[0], i))?
-                );
-            }
+        keypairs.push(keypair.unwrap_or_else(|e| {
+            eprintln!(
+                "\n{}\n",
+                color_err(&format!("ERROR: Invalid key on line {} of stdin: {}", line_number, e), COLOR_RED)
+            );
+            std::process::exit(-1);
+        }));
+    }
 
-            let data_count = Self::decode_compact_u16(r)?;
+    keypairs
+}
 
-            if data_count > MAXIMUM_INSTRUCTION_DATA_COUNT {
-                return Err(Some(format!(
-                    "Too many data bytes in instruction {}: expected at most {} got {}",
-                    i, MAXIMUM_INSTRUCTION_DATA_COUNT, data_count
-                )));
-            }
+// Reads keys from a file of mnemonics, for --mnemonic-file, exiting if any is invalid.  Each non-empty line is a
+// mnemonic, optionally followed by a tab and its passphrase, and then by another tab and its derivation path.  Those
+// that aren't given are passphrase and derivation_path, and a mnemonic without either derivation path is an error.  A
+// mnemonic that isn't valid BIP39 is only used if assume_yes is set.
+fn read_mnemonic_file(
+    path : &str,
+    passphrase : &str,
+    derivation_path : Option<&derivation_path::DerivationPath>,
+    assume_yes : bool
+) -> Vec<ed25519_dalek::Keypair>
+{
+    let contents = read_secret_file(path);
 
-            let mut data = vec![0_u8; data_count as usize];
+    let mut keypairs = vec![];
 
-            Self::read(r, &mut data)?;
+    for (line_number, line) in (1..).zip(contents.lines()) {
+        if line.trim().is_empty() {
+            continue;
+        }
 
-            ret.instructions.push(Instruction { program_address : program_address.0, addresses, data });
-        }
+        let mut fields = line.split('\t');
 
-        Ok(ret)
+        let mnemonic = fields.next().unwrap_or_default().trim();
+
+        // Spaces may be part of a passphrase, so it is used as is
+        let passphrase = fields.next().unwrap_or(passphrase);
+
+        let keypair =
+            validate_mnemonic_unless_assumed(mnemonic, assume_yes, &format!("line {} of {}", line_number, path))
+                .and_then(|_| {
+                    let derivation_path = match fields.next().map(str::trim) {
+                        Some(value) => {
+                            value.parse().map_err(|e| format!("Invalid derivation path {}: {}", value, e))?
+                        },
+                        None => derivation_path.cloned().ok_or("No derivation path given, and no --derivation-path")?
+                    };
+                    let seed = generate_seed_from_seed_phrase_and_passphrase(mnemonic, passphrase);
+                    keypair_from_seed_and_derivation_path(&seed, derivation_path)
+                });
+
+        keypairs.push(keypair.unwrap_or_else(|e| {
+            eprintln!(
+                "\n{}\n",
+                color_err(&format!("ERROR: Invalid mnemonic on line {} of {}: {}", line_number, path, e), COLOR_RED)
+            );
+            std::process::exit(-1);
+        }));
     }
 
-    // Return the message bytes of the transaction.
-    pub fn message(
-        &self,
-        w : &mut dyn std::io::Write
-    ) -> Result<(), String>
-    {
-        u8::try_from(self.signed_read_write_addresses.len() + self.signed_read_only_addresses.len())
-            .or(Err("Too many signed addresses".to_string()))
-            .and_then(|u| Self::write(w, &[u]))?;
+    keypairs
+}
 
-        u8::try_from(self.signed_read_only_addresses.len())
-            .or(Err("Too many read only addresses".to_string()))
-            .and_then(|u| Self::write(w, &[u]))?;
+// A keyring file starts with these bytes and a version number, followed by the salt that its passphrase is hashed
+// with by Argon2 to make the key, the nonce, and then the keypairs encrypted with ChaCha20-Poly1305.  Everything before
+// the encrypted keypairs is authenticated along with them.
+#[cfg(feature = "keyring")]
+const KEYRING_MAGIC : &[u8; 8] = b"SOLSIGNK";
 
-        Self::write(w, &[self.unsigned_read_only_addresses.len() as u8])?;
+#[cfg(feature = "keyring")]
+const KEYRING_VERSION : u8 = 1;
 
-        let recent_blockhash = self.recent_blockhash.as_ref().unwrap_or(&EMPTY_RECENT_BLOCKHASH);
+#[cfg(feature = "keyring")]
+const KEYRING_SALT_BYTES : usize = 16;
 
-        if self.instructions.len() > (u16::MAX as usize) {
-            return Err("Too many instructions".to_string());
-        }
+#[cfg(feature = "keyring")]
+const KEYRING_NONCE_BYTES : usize = 12;
 
-        // compact-array of account addresses
-        Self::encode_compact_u16(
-            (self.signed_read_write_addresses.len() +
-                self.signed_read_only_addresses.len() +
-                self.unsigned_read_write_addresses.len() +
-                self.unsigned_read_only_addresses.len()) as u16,
-            w
-        )?;
+#[cfg(feature = "keyring")]
+const KEYRING_HEADER_BYTES : usize = KEYRING_MAGIC.len() + 1 + KEYRING_SALT_BYTES + KEYRING_NONCE_BYTES;
 
-        for a in self
-            .signed_read_write_addresses
-            .iter()
-            .chain(self.signed_read_only_addresses.iter())
-            .map(|s| &s.pubkey.0)
-            .chain(
-                self.unsigned_read_write_addresses.iter().chain(self.unsigned_read_only_addresses.iter()).map(|a| &a.0)
-            )
-        {
-            Self::write(w, a)?;
-        }
+#[cfg(feature = "keyring")]
+fn keyring_cipher(
+    passphrase : &str,
+    salt : &[u8]
+) -> Result<chacha20poly1305::ChaCha20Poly1305, String>
+{
+    use chacha20poly1305::KeyInit;
 
-        // recent blockhash
-        Self::write(w, &recent_blockhash.0)?;
+    let mut key = zeroize::Zeroizing::new([0_u8; 32]);
 
-        // instructions
-        Self::encode_compact_u16(self.instructions.len() as u16, w)?;
+    argon2::Argon2::default().hash_password_into(passphrase.as_bytes(), salt, &mut *key).map_err(|e| e.to_string())?;
 
-        for instruction in &self.instructions {
-            // instruction program_id index
-            Self::write(
-                w,
-                std::slice::from_ref(&self.find_address_index(&instruction.program_address).ok_or(format!(
-                    "Invalid Transaction - program address {} not in address list",
-                    instruction.program_address
-                ))?)
-            )?;
+    Ok(chacha20poly1305::ChaCha20Poly1305::new(&(*key).into()))
+}
 
-            // instruction address indices
-            Self::encode_compact_u16(instruction.addresses.len() as u16, w)?;
-            for a in &instruction.addresses {
-                Self::write(
-                    w,
-                    std::slice::from_ref(
-                        &self
-                            .find_address_index(&a.0)
-                            .ok_or(format!("Invalid Transaction - address {} is not in address list", a.0))?
-                    )
-                )?;
-            }
+// Decrypts the keypairs held in a keyring file
+#[cfg(feature = "keyring")]
+fn read_keyring(
+    path : &str,
+    passphrase : &str
+) -> Result<Vec<ed25519_dalek::Keypair>, String>
+{
+    use chacha20poly1305::aead::{Aead, Payload};
 
-            // instruction data
-            let data_len = instruction.data.len();
-            if data_len > (MAXIMUM_INSTRUCTION_DATA_COUNT as usize) {
-                return Err(format!(
-                    "Instruction data len too long: {} > {}",
-                    data_len, MAXIMUM_INSTRUCTION_DATA_COUNT
-                ));
-            }
-            Self::encode_compact_u16(data_len as u16, w)?;
-            Self::write(w, instruction.data.as_slice())?;
-        }
-        Ok(())
+    let contents = std::fs::read(path).map_err(|e| e.to_string())?;
+
+    if (contents.len() < KEYRING_HEADER_BYTES) || !contents.starts_with(KEYRING_MAGIC) {
+        return Err("Not a solsign keyring".to_string());
     }
 
-    // Iterates over addresses that still need to provide a signature
-    pub fn needed_signatures(&self) -> impl Iterator<Item = Pubkey>
-    {
-        let mut v : Vec<Pubkey> = self
-            .signed_read_write_addresses
-            .iter()
-            .filter_map(|a| {
-                if a.signature.is_some() {
-                    None
-                }
-                else {
-                    Some(a.pubkey.clone())
-                }
-            })
-            .chain(self.signed_read_only_addresses.iter().filter_map(|a| {
-                if a.signature.is_some() {
-                    None
-                }
-                else {
-                    Some(a.pubkey.clone())
-                }
-            }))
-            .collect();
+    let (header, encrypted) = contents.split_at(KEYRING_HEADER_BYTES);
 
-        v.sort_by_key(|a| format!("{}", a));
+    let version = header[KEYRING_MAGIC.len()];
+    if version != KEYRING_VERSION {
+        return Err(format!("Unsupported keyring version {}", version));
+    }
 
-        v.dedup();
+    let (salt, nonce) = header[(KEYRING_MAGIC.len() + 1)..].split_at(KEYRING_SALT_BYTES);
 
-        v.into_iter()
+    let keypairs_bytes = keyring_cipher(passphrase, salt)?
+        .decrypt(nonce.into(), Payload { msg : encrypted, aad : header })
+        .map(zeroize::Zeroizing::new)
+        .map_err(|_| "Wrong passphrase, or the keyring has been damaged".to_string())?;
+
+    keypairs_bytes
+        .chunks(ed25519_dalek::KEYPAIR_LENGTH)
+        .map(|bytes| ed25519_dalek::Keypair::from_bytes(bytes).map_err(|e| e.to_string()))
+        .collect()
+}
+
+// Encrypts keypairs into a new keyring file, made with create_secret_file.  An existing file is never overwritten.
+#[cfg(feature = "keyring")]
+fn write_keyring(
+    path : &str,
+    passphrase : &str,
+    keypairs : &[&ed25519_dalek::Keypair]
+) -> Result<(), String>
+{
+    use chacha20poly1305::aead::{Aead, Payload};
+
+    let mut header = KEYRING_MAGIC.to_vec();
+    header.push(KEYRING_VERSION);
+
+    let mut salt_and_nonce = [0_u8; KEYRING_SALT_BYTES + KEYRING_NONCE_BYTES];
+    getrandom::getrandom(&mut salt_and_nonce).map_err(|e| e.to_string())?;
+    header.extend_from_slice(&salt_and_nonce);
+
+    let (salt, nonce) = salt_and_nonce.split_at(KEYRING_SALT_BYTES);
+
+    // Allocated at its full size up front, so that growing it never leaves copies of the keys behind
+    let mut keypairs_bytes =
+        zeroize::Zeroizing::new(Vec::with_capacity(keypairs.len() * ed25519_dalek::KEYPAIR_LENGTH));
+    for keypair in keypairs {
+        keypairs_bytes.extend_from_slice(&*zeroize::Zeroizing::new(keypair.to_bytes()));
     }
 
-    pub fn sign(
-        &mut self,
-        pubkey : &Pubkey,
-        signature : ed25519_dalek::Signature
-    ) -> Result<(), String>
-    {
-        for i in 0..self.signed_read_write_addresses.len() {
-            if self.signed_read_write_addresses[i].pubkey == *pubkey {
-                self.signed_read_write_addresses[i].signature = Some(signature);
-            }
+    let encrypted = keyring_cipher(passphrase, salt)?
+        .encrypt(nonce.into(), Payload { msg : &keypairs_bytes, aad : &header })
+        .map_err(|e| e.to_string())?;
+
+    create_secret_file(std::path::Path::new(path))
+        .and_then(|mut file| file.write_all(&header).and_then(|_| file.write_all(&encrypted)))
+        .map_err(|e| e.to_string())
+}
+
+// Prompts for the passphrase of a keyring and returns its keypairs, exiting if they can't be read
+#[cfg(feature = "keyring")]
+fn open_keyring(path : &str) -> Vec<ed25519_dalek::Keypair>
+{
+    let passphrase = prompt_password(format!("\n  Enter the passphrase of keyring {}: ", path))
+        .map(zeroize::Zeroizing::new)
+        .unwrap_or_else(|_| exit_at_end_of_input());
+
+    read_keyring(path, &passphrase).unwrap_or_else(|e| {
+        eprintln!("\n{}\n", color_err(&format!("ERROR: Failed to read keyring {}: {}", path, e), COLOR_RED));
+        std::process::exit(-1);
+    })
+}
+
+// Prompts for a new passphrase, twice, and writes the keypairs to a new keyring encrypted with it, exiting if it can't
+// be written
+#[cfg(feature = "keyring")]
+fn create_keyring(
+    path : &str,
+    keypairs : &[&ed25519_dalek::Keypair]
+)
+{
+    let passphrase = loop {
+        let passphrase = prompt_password(format!("\n  Enter a passphrase for new keyring {}: ", path))
+            .map(zeroize::Zeroizing::new)
+            .unwrap_or_else(|_| exit_at_end_of_input());
+        if passphrase.is_empty() {
+            println!("\n  {}", color_out("A keyring must have a passphrase, try again.", COLOR_RED));
+            continue;
         }
+        let again = prompt_password("  Enter the passphrase again: ")
+            .map(zeroize::Zeroizing::new)
+            .unwrap_or_else(|_| exit_at_end_of_input());
+        if again == passphrase {
+            break passphrase;
+        }
+        println!("\n  {}", color_out("Passphrases do not match, try again.", COLOR_RED));
+    };
 
-        for i in 0..self.signed_read_only_addresses.len() {
-            if self.signed_read_only_addresses[i].pubkey == *pubkey {
-                self.signed_read_only_addresses[i].signature = Some(signature);
-            }
+    write_keyring(path, &passphrase, keypairs).unwrap_or_else(|e| {
+        eprintln!("\n{}\n", color_err(&format!("ERROR: Failed to write keyring {}: {}", path, e), COLOR_RED));
+        std::process::exit(-1);
+    });
+
+    println!("\n  Wrote {} keys to keyring {}", keypairs.len(), path);
+}
+
+// Set by --insecure-echo, so that every password prompt can fall back to reading with echo
+static INSECURE_ECHO : std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+// Prompts for and reads a password, passphrase or mnemonic from the terminal without echoing it.  If that fails, as it
+// does where the terminal can't be set not to echo, then only with --insecure-echo is it read from stdin instead, with
+// echo, after warning that it will be shown.
+fn prompt_password(prompt : impl ToString) -> std::io::Result<String>
+{
+    let prompt = prompt.to_string();
+
+    rpassword::prompt_password(&prompt).or_else(|e| {
+        if !INSECURE_ECHO.load(std::sync::atomic::Ordering::Relaxed) {
+            return Err(e);
         }
 
-        Ok(())
+        print_warning("insecure-echo", &format!("Input can't be hidden ({}), so what is typed will be shown.", e));
+        print!("{}", prompt.trim_start_matches('\n'));
+        let _ = std::io::stdout().flush();
+
+        read_stdin_line().ok_or_else(|| std::io::Error::from(std::io::ErrorKind::UnexpectedEof))
+    })
+}
+
+// Reads from a file descriptor up to the first newline, one byte at a time so that nothing past the newline is
+// consumed.  The file is never dropped, as the file descriptor isn't solsign's to close; a file descriptor that isn't
+// open just fails to read.
+#[cfg(unix)]
+fn read_fd_line(
+    fd : u32,
+    line : &mut Vec<u8>
+) -> std::io::Result<()>
+{
+    use std::os::unix::io::FromRawFd;
+
+    let fd = i32::try_from(fd).map_err(|_| std::io::Error::from(std::io::ErrorKind::InvalidInput))?;
+
+    let file = std::mem::ManuallyDrop::new(unsafe { std::fs::File::from_raw_fd(fd) });
+
+    let mut byte = [0_u8; 1];
+    while ((&*file).read(&mut byte)? == 1) && (byte[0] != b'\n') {
+        line.push(byte[0]);
     }
 
-    pub fn encode(
-        &self,
-        w : &mut dyn std::io::Write
-    ) -> Result<(), String>
-    {
-        let total_signatures = self.signed_read_write_addresses.len() + self.signed_read_only_addresses.len();
+    Ok(())
+}
 
-        if total_signatures > (u16::MAX as usize) {
-            return Err("Too many addresses".to_string());
-        }
+#[cfg(not(unix))]
+fn read_fd_line(
+    _fd : u32,
+    _line : &mut Vec<u8>
+) -> std::io::Result<()>
+{
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "file descriptors can only be read on Unix"))
+}
 
-        Self::encode_compact_u16(total_signatures as u16, w)?;
+// Reads the challenge password from the first line of a file descriptor, for --password-fd, exiting if it can't be
+// read.  Only that line is read, so anything following it is left for whatever else reads the file descriptor.
+fn read_password_fd(fd : u32) -> String
+{
+    let mut password = vec![];
 
-        for signature in self.signed_read_write_addresses.iter().chain(&self.signed_read_only_addresses) {
-            Self::encode_signature(signature.signature, w)?;
-        }
+    let result = read_fd_line(fd, &mut password);
 
-        self.message(w)
+    if let Err(e) = result {
+        eprintln!(
+            "\n{}\n",
+            color_err(&format!("ERROR: Failed to read password from file descriptor {}: {}", fd, e), COLOR_RED)
+        );
+        std::process::exit(-1);
     }
 
-    fn decode_compact_u16(r : &mut dyn std::io::Read) -> Result<u16, Option<String>>
-    {
-        let mut buf = [0_u8; 3];
+    if password.last() == Some(&b'\r') {
+        password.pop();
+    }
+
+    String::from_utf8(password).unwrap_or_else(|_| {
+        eprintln!(
+            "\n{}\n",
+            color_err(&format!("ERROR: Password read from file descriptor {} is not valid UTF-8", fd), COLOR_RED)
+        );
+        std::process::exit(-1);
+    })
+}
 
-        Self::read(r, &mut buf[0..1])?;
+// Reads the contents of a file holding a mnemonic or passphrase, exiting if it can't be read
+fn read_secret_file(path : &str) -> String
+{
+    std::fs::read_to_string(path).unwrap_or_else(|e| {
+        eprintln!("\n{}\n", color_err(&format!("ERROR: Failed to read {}: {}", path, e), COLOR_RED));
+        std::process::exit(-1);
+    })
+}
 
-        if (buf[0] & 0x80) == 0x80 {
-            Self::read(r, &mut buf[1..2])?;
-            if buf[1] & 0x80 == 0x80 {
-                Self::read(r, &mut buf[2..3])?;
-                Ok((((buf[0] as u16) & !0x80) << 0) |
-                    (((buf[1] as u16) & !0x80) << 7) |
-                    (((buf[2] as u16) & !0x00) << 14))
-            }
-            else {
-                Ok((((buf[0] as u16) & !0x80) << 0) | (((buf[1] as u16) & !0x80) << 7))
-            }
-        }
-        else {
-            Ok(buf[0] as u16)
+// Generates a new key from the operating system's secure random number generator, for --generate, and writes it as a
+// solana command line compatible key file at path, which must not already exist, made with create_secret_file.  The
+// public key is then printed.  Exits if the key file can't be written.
+fn generate_key_file(path : &str)
+{
+    let fail = |e : String| -> ! {
+        eprintln!("\n{}\n", color_err(&format!("ERROR: Failed to generate key file {}: {}", path, e), COLOR_RED));
+        std::process::exit(-1);
+    };
+
+    let mut seed = zeroize::Zeroizing::new([0_u8; ed25519_dalek::SECRET_KEY_LENGTH]);
+    getrandom::getrandom(&mut *seed).unwrap_or_else(|e| fail(e.to_string()));
+
+    let keypair = keypair_from_seed(&*seed).unwrap_or_else(|e| fail(e));
+
+    // Each byte takes at most 4 characters, "255,", so the contents are never reallocated, which would leave a copy of
+    // the secret key behind
+    let mut contents = zeroize::Zeroizing::new(String::with_capacity((ed25519_dalek::KEYPAIR_LENGTH * 4) + 2));
+    contents.push('[');
+    for (i, byte) in zeroize::Zeroizing::new(keypair.to_bytes()).iter().enumerate() {
+        if i > 0 {
+            contents.push(',');
         }
+        let _ = std::fmt::Write::write_fmt(&mut *contents, format_args!("{}", byte));
     }
+    contents.push(']');
 
-    fn decode_signature_from_header(
-        signatures : impl IntoIterator<Item = Option<ed25519_dalek::Signature>>,
-        r : &mut dyn std::io::Read
-    ) -> Result<PubkeyWithSignature, Option<String>>
-    {
-        let address = Self::decode_address(r)?;
+    let path = expand_path(path).unwrap_or_else(|e| fail(e));
 
-        Ok(PubkeyWithSignature {
-            pubkey : Pubkey(address.0),
-            signature : signatures.into_iter().next().unwrap_or(None)
-        })
-    }
+    create_secret_file(&path)
+        .and_then(|mut file| file.write_all(contents.as_bytes()))
+        .unwrap_or_else(|e| fail(e.to_string()));
 
-    fn decode_address(r : &mut dyn std::io::Read) -> Result<Address, Option<String>>
-    {
-        let mut buf = [0_u8; 32];
-        Self::read(r, &mut buf)?;
-        Ok(Address(buf))
-    }
+    println!("{}", Pubkey(keypair.public.to_bytes()));
+}
 
-    fn decode_recent_blockhash(r : &mut dyn std::io::Read) -> Result<Option<Sha256Digest>, Option<String>>
-    {
-        let mut buf = [0_u8; 32];
+// Creates a new file to write secrets to, such as keys, failing if the file already exists rather than replacing what
+// may be someone's only copy of a key.  The file is readable and writable only by its owner from the moment it is
+// created, so there is no window in which another user could open it.  Only Unix lets that be set through the standard
+// library; elsewhere, including on Windows, the file would get whatever permissions its directory gives new files, so
+// no file is written at all.
+#[cfg(unix)]
+fn create_secret_file(path : &std::path::Path) -> std::io::Result<std::fs::File>
+{
+    let mut open_options = std::fs::OpenOptions::new();
+    open_options.write(true).create_new(true);
+    std::os::unix::fs::OpenOptionsExt::mode(&mut open_options, 0o600);
 
-        Self::read(r, &mut buf)?;
+    open_options.open(path)
+}
 
-        if buf == EMPTY_RECENT_BLOCKHASH.0 {
-            Ok(None)
+#[cfg(not(unix))]
+fn create_secret_file(path : &std::path::Path) -> std::io::Result<std::fs::File>
+{
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        format!("Not writing {}, as it can't be made readable only by its owner on this system", path.display())
+    ))
+}
+
+// Expands a leading ~ to the home directory, and $NAME or ${NAME} to the value of the environment variable NAME, as a
+// shell would have done.  Key file paths taken from solana's config, or passed along by another program rather than a
+// shell, may not have been expanded.
+fn expand_path(path : &str) -> Result<std::path::PathBuf, String>
+{
+    let env_var = |name : &str| std::env::var(name).map_err(|_| format!("environment variable {} is not set", name));
+
+    let mut expanded = String::new();
+
+    let mut rest = path;
+
+    if rest == "~" || rest.starts_with("~/") {
+        expanded.push_str(&env_var("HOME")?);
+        rest = &rest[1..];
+    }
+
+    while let Some(index) = rest.find('$') {
+        expanded.push_str(&rest[..index]);
+        rest = &rest[(index + 1)..];
+
+        if let Some(braced) = rest.strip_prefix('{') {
+            let end = braced.find('}').ok_or_else(|| format!("unterminated ${{ in {}", path))?;
+            expanded.push_str(&env_var(&braced[..end])?);
+            rest = &braced[(end + 1)..];
         }
         else {
-            Ok(Some(Sha256Digest(buf)))
+            let end = rest.find(|c : char| !c.is_ascii_alphanumeric() && (c != '_')).unwrap_or(rest.len());
+            // A $ not followed by a name is just a $
+            if end == 0 {
+                expanded.push('$');
+            }
+            else {
+                expanded.push_str(&env_var(&rest[..end])?);
+            }
+            rest = &rest[end..];
         }
     }
 
-    // Searching is done irrespective of account permissions.  This matches the expected Solana runtime behavior,
-    // where the execution system will perform a similar action.  It is technically possible to encode the same
-    // address with multiple permissions versions, but the runtime will reject such a transaction with an error about
-    // "Account loaded twice"
-    fn find_address_index(
-        &self,
-        address : &Address
-    ) -> Option<u8>
-    {
-        match self.signed_read_write_addresses.iter().position(|s| address == &s.pubkey) {
-            Some(index) => return Some(index as u8),
-            None => ()
-        }
+    expanded.push_str(rest);
 
-        let mut offset = self.signed_read_write_addresses.len();
+    Ok(expanded.into())
+}
 
-        match self.signed_read_only_addresses.iter().position(|s| address == &s.pubkey) {
-            Some(index) => return Some((index + offset) as u8),
-            None => ()
-        }
+// Returns the paths of all .json files within a directory, sorted so that keys are loaded in a stable order
+fn key_files_in_directory(directory : &std::path::Path) -> Vec<std::path::PathBuf>
+{
+    let entries = std::fs::read_dir(directory).unwrap_or_else(|e| {
+        eprintln!(
+            "\n{}\n",
+            color_err(&format!("ERROR: Failed to read key directory {}: {}", directory.display(), e), COLOR_RED)
+        );
+        std::process::exit(-1);
+    });
 
-        offset += self.signed_read_only_addresses.len();
+    let mut paths : Vec<std::path::PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && path.extension().is_some_and(|extension| extension == "json"))
+        .collect();
 
-        match self.unsigned_read_write_addresses.iter().position(|a| address == a) {
-            Some(index) => return Some((index + offset) as u8),
-            None => ()
+    paths.sort();
+
+    paths
+}
+
+// Lists the loaded keys by index and reads the user's selection of one of them.  Returns None if the user pressed
+// ENTER without selecting a key.
+fn select_loaded_key(keys_in_order : &[Pubkey]) -> Option<usize>
+{
+    loop {
+        println!("\n  Loaded Keys:\n");
+
+        for (i, key) in keys_in_order.iter().enumerate() {
+            let padding = if i > 9 { " ".to_string() } else { "  ".to_string() };
+            println!("   ({}){}{}", i, padding, key);
         }
 
-        offset += self.unsigned_read_write_addresses.len();
+        print!("\n  Select a key 0 - {} from above, or press ENTER to skip: ", keys_in_order.len() - 1);
+        let _ = std::io::stdout().flush();
 
-        match self.unsigned_read_only_addresses.iter().position(|a| address == a) {
-            Some(index) => Some((index + offset) as u8),
-            None => None
+        let line = read_stdin_line().unwrap_or_else(|| exit_at_end_of_input());
+
+        let line = line.replace("\n", "").replace("\r", "");
+
+        if line.is_empty() {
+            return None;
+        }
+
+        match line.parse::<usize>() {
+            Ok(selection) if selection < keys_in_order.len() => return Some(selection),
+            _ => println!("\n\n  {}\n", color_out("Invalid selection, try again.", COLOR_RED))
         }
     }
+}
 
-    // Returns (address, is_signed, read_write)
-    fn find_address_at_index(
-        &self,
-        index : u8
-    ) -> Option<(Address, bool, bool)>
-    {
-        let mut uindex = index as usize;
+// If a challenge password was set, require the user to enter it before proceeding, exiting if they fail to do so.
+// Returns false if the user ends input (i.e. Ctrl-D) instead of entering the password, so that just this signing is
+// abandoned.  The prompt itself is on the terminal, and everything else is written to stderr, so that nothing is
+// mixed into the transactions written to stdout.
+fn challenge_password(
+    password : &str,
+    show : &mut dyn FnMut()
+) -> bool
+{
+    if password.is_empty() {
+        return true;
+    }
 
-        if uindex < self.signed_read_write_addresses.len() {
-            return Some((Address(self.signed_read_write_addresses[uindex].pubkey.0), true, true));
+    eprintln!("\n");
+    let mut attempts = 0;
+    loop {
+        let prompt = format!(
+            "  Enter challenge password ({} attempt{} remaining): ",
+            (5 - attempts),
+            if attempts == 4 { "" } else { "s" }
+        );
+        let password_attempt = match prompt_password(prompt) {
+            Ok(password_attempt) => password_attempt,
+            Err(_) => {
+                eprintln!("\n\n  {}", color_err("Password not entered; not signing.", COLOR_YELLOW));
+                return false;
+            }
+        };
+
+        if password_attempt == password {
+            eprintln!();
+            return true;
+        }
+
+        // The :show command re-displays what is about to be signed, which may have scrolled off screen, without using
+        // up an attempt
+        if password_attempt == ":show" {
+            show();
+            eprintln!();
+            continue;
         }
 
-        uindex -= self.signed_read_write_addresses.len();
+        if attempts == 4 {
+            eprintln!("\n  {}\n", color_err("Password challenge failed.", COLOR_RED));
+            std::process::exit(0);
+        }
+
+        attempts += 1;
+    }
+}
+
+// Solana's off-chain messages start with this, which no transaction message can, as its first byte would say that the
+// message was of a version that doesn't exist.  Signing a message with it in front means the signature can never be
+// taken as a signature of a transaction, however the message was crafted.
+const OFFCHAIN_MESSAGE_SIGNING_DOMAIN : &[u8] = b"\xffsolana offchain";
+
+// Signing domain, version, format and length
+const OFFCHAIN_MESSAGE_HEADER_BYTES : usize = 16 + 1 + 1 + 2;
+
+// Messages this long or shorter can be signed by a Ledger, so are given the formats that say so
+const OFFCHAIN_MESSAGE_MAXIMUM_LEDGER_BYTES : usize =
+    (MAXIMUM_TRANSACTION_BYTES as usize) - OFFCHAIN_MESSAGE_HEADER_BYTES;
+
+const OFFCHAIN_MESSAGE_MAXIMUM_BYTES : usize = (u16::MAX as usize) - OFFCHAIN_MESSAGE_HEADER_BYTES;
+
+// Serializes message as a version 0 Solana off-chain message, which is what is signed, in the same way as
+// solana sign-offchain-message does, so that the signature can be checked with solana verify-offchain-signature
+fn offchain_message(message : &str) -> Result<Vec<u8>, String>
+{
+    let bytes = message.as_bytes();
+
+    // Restricted ASCII, limited UTF-8 or extended UTF-8
+    let format : u8 = if bytes.len() > OFFCHAIN_MESSAGE_MAXIMUM_BYTES {
+        return Err(format!(
+            "Message too long: expected at most {} bytes, got {}",
+            OFFCHAIN_MESSAGE_MAXIMUM_BYTES,
+            bytes.len()
+        ));
+    }
+    else if bytes.len() > OFFCHAIN_MESSAGE_MAXIMUM_LEDGER_BYTES {
+        2
+    }
+    else if bytes.iter().all(|b| (0x20..=0x7E).contains(b)) {
+        0
+    }
+    else {
+        1
+    };
+
+    Ok([OFFCHAIN_MESSAGE_SIGNING_DOMAIN, &[0, format], &(bytes.len() as u16).to_le_bytes(), bytes].concat())
+}
+
+// Reads messages from stdin, one per line, and signs each, as a Solana off-chain message, with a key chosen from the
+// loaded keys.  Stops after the first message if no_prompt is set.
+fn sign_messages(
+    keys : &HashMap<Pubkey, ed25519_dalek::Keypair>,
+    keys_in_order : &[Pubkey],
+    password : &str,
+    no_prompt : bool
+)
+{
+    loop {
+        println!("\n  Enter message to sign:\n");
+
+        let line = read_stdin_line().unwrap_or_else(|| exit_at_end_of_input());
+
+        let message = line.trim_end_matches(['\n', '\r']);
+
+        if message.is_empty() {
+            continue;
+        }
+
+        let offchain_message = match offchain_message(message) {
+            Ok(offchain_message) => offchain_message,
+            Err(e) => {
+                eprintln!("\n  {}", color_err(&e, COLOR_RED));
+                continue;
+            }
+        };
+
+        // Only ask which key to use if there is more than one to choose from
+        let selection = if keys_in_order.len() == 1 {
+            0
+        }
+        else {
+            match select_loaded_key(keys_in_order) {
+                Some(selection) => selection,
+                None => continue
+            }
+        };
+
+        if !challenge_password(password, &mut || println!("\n  Message:\n\n    {}", message)) {
+            continue;
+        }
+
+        let pubkey = &keys_in_order[selection];
+
+        let signature = keys[pubkey].sign(&offchain_message);
+
+        println!("\n  Message signed by:\n\n    {}", pubkey);
+        println!("\n  Signature:\n\n   {}", color_out(&bs58::encode(signature.to_bytes()).into_string(), COLOR_GREEN));
+
+        // no_prompt stops after the first message
+        if no_prompt {
+            println!();
+            break;
+        }
+    }
+}
+
+// How long input at a terminal may be left incomplete before the user is asked whether to clear it
+const INCOMPLETE_INPUT_TIMEOUT_SECONDS : u64 = 5;
+
+// Set by --test-seed, so that nothing that solsign does depends on how long input takes to arrive
+static TEST_MODE : std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+// Reads lines from stdin until either a complete Base64 encoded transaction has been read in, or a command line has
+// been read.  Returns None if the input was not a valid transaction, and Input::End if stdin is closed before any of a
+// transaction is read.  If stdin is closed part way through a transaction, what was read is decoded as if an empty line
+// had been read, so that a complete transaction is still returned, and if it isn't complete, exits with an error.  A
+// line holding only '.' says that the transaction has been completely entered, so that input which is still incomplete
+// is reported as an error rather than waiting for more.
+fn read_input() -> Option<Input>
+{
+    // Base64 characters read but not yet decoded, because they don't yet make up a complete group of 4 characters
+    let mut pending = "".to_string();
+
+    // All characters read, kept in case the input turns out to be Base58 encoded, which can't be decoded a piece at
+    // a time
+    let mut text = "".to_string();
+
+    // The bytes decoded thus far.  Only newly read characters are decoded and appended to this as each line is read,
+    // rather than decoding all characters read thus far every time.
+    let mut bytes = Vec::<u8>::new();
+
+    // Cleared once a character that Base58 doesn't use is read
+    let mut could_be_base58 = true;
+
+    // Set when what has been read so far is the start of a transaction
+    let mut incomplete = false;
+
+    let times_out = std::io::stdin().is_terminal() && !TEST_MODE.load(std::sync::atomic::Ordering::Relaxed);
+
+    loop {
+        // Someone typing or pasting at a terminal may have pasted only part of a transaction, so rather than wait
+        // without end for the rest, they are asked what to do if it doesn't come
+        let line = if incomplete && times_out {
+            loop {
+                match read_stdin_line_within(Some(std::time::Duration::from_secs(INCOMPLETE_INPUT_TIMEOUT_SECONDS))) {
+                    Ok(line) => break line,
+                    Err(()) => {
+                        if ask_clear_incomplete(bytes.len()) {
+                            eprintln!("\n  Clearing tx data, start again.");
+                            return None;
+                        }
+                        println!();
+                    }
+                }
+            }
+        }
+        else {
+            read_stdin_line()
+        };
+
+        let (mut line, at_end) = match line {
+            Some(line) => (line, false),
+            None if text.is_empty() => return Some(Input::End),
+            None => ("".to_string(), true)
+        };
+
+        line.retain(|c| !is_ignored_in_input(c));
+
+        // '.' is neither a Base64 nor a Base58 character, so can't be part of a transaction
+        let done = line == ".";
+        if done {
+            if text.is_empty() {
+                return None;
+            }
+            line.clear();
+        }
+
+        // A command can only be given in place of a transaction, not in the middle of one.  ':' is not a Base64
+        // character, so it's not possible for a command to be mistaken for the start of a transaction.
+        if bytes.is_empty() && pending.is_empty() && line.starts_with(':') {
+            return Some(Input::Command(line));
+        }
+
+        // Decoding Base58 takes time that grows with the square of its length, so rather than decoding all of it
+        // again at every line, it is only decoded once the first line is read, which is all there is of a transaction
+        // that isn't wrapped, and once no more is coming
+        could_be_base58 &= line.chars().all(|c| BASE58_ALPHABET.contains(c));
+        let decode_base58 = could_be_base58 && (text.is_empty() || line.is_empty());
+
+        pending.push_str(&line);
+        text.push_str(&line);
+
+        let base58_tx = || if decode_base58 { decode_base58_transaction(&text) } else { None };
+
+        // Decode all complete groups of characters.  An empty line means that no more characters are coming, so
+        // decode everything, which will fail if there is an incomplete group.
+        let decodable = if line.is_empty() { pending.len() } else { pending.len() - (pending.len() % 4) };
+
+        if let Err(e) = base64::decode_config_buf(&pending[..decodable], base64::STANDARD, &mut bytes) {
+            // Input that isn't valid Base64 may still be valid Base58
+            if let Some(decoded_tx) = base58_tx() {
+                return Some(Input::Transaction(decoded_tx));
+            }
+            eprintln!("  {}", color_err(&format!("Invalid Base64 input: {}", e), COLOR_RED));
+            eprintln!("\n  Clearing tx data, start again.");
+            return None;
+        }
+
+        pending.drain(..decodable);
+
+        // Attempt a decode.  Might be short because not all lines of the transaction have been provided yet.
+        match (Transaction::decode(&mut std::io::Cursor::new(&bytes)), base58_tx()) {
+            // A complete transaction in either encoding is ambiguous, so let the user say which they meant
+            (Ok(base64_tx), Some(base58_tx)) => {
+                return Some(Input::Transaction(if ask_is_base58() { base58_tx } else { base64_tx }));
+            },
+
+            // A completely decoded transaction was found
+            (Ok(decoded_tx), None) | (Err(_), Some(decoded_tx)) => return Some(Input::Transaction(decoded_tx)),
+
+            // Incomplete data with no more to come
+            (Err(TxError::Truncated), None) if at_end => exit_at_end_of_input_within_transaction(),
+
+            // Incomplete data when the user has said that there is no more
+            (Err(TxError::Truncated), None) if done => {
+                eprintln!("\n  {}", color_err("Transaction entered is incomplete.", COLOR_RED));
+                eprintln!("\n  Clearing tx data, start again.");
+                return None;
+            },
+
+            // Incomplete data, so continue reading lines
+            (Err(TxError::Truncated), None) => incomplete = true,
+
+            // Base58 can't be decoded until all of it has been read, so input that might still be Base58 is read
+            // until an empty line
+            (Err(_), None) if could_be_base58 && !line.is_empty() => (),
+
+            // If an error occurred, then input was bad
+            (Err(e), None) => {
+                eprintln!("\n  {}", color_err(&format!("Invalid transaction: {}", e), COLOR_RED));
+                eprintln!("\n  Clearing tx data, start again.");
+                return None;
+            }
+        }
+    }
+}
+
+// Base58 excludes the Base64 characters '0', 'O', 'I', 'l', '+', '/' and '=', so the presence of any of these means
+// that the input can only be Base64
+fn is_base58(text : &str) -> bool
+{
+    !text.is_empty() && text.chars().all(|c| BASE58_ALPHABET.contains(c))
+}
+
+// Returns the transaction encoded in text as Base58, if text is a complete Base58 encoded transaction
+fn decode_base58_transaction(text : &str) -> Option<Transaction>
+{
+    if !is_base58(text) {
+        return None;
+    }
+
+    let bytes = bs58::decode(text).into_vec().ok()?;
+
+    Transaction::decode(&mut bytes.as_slice()).ok()
+}
+
+// Asks the user whether to clear input that has been incomplete for a while, rather than keep waiting for the rest of
+// it.  Exits if stdin has been closed.
+fn ask_clear_incomplete(bytes_count : usize) -> bool
+{
+    loop {
+        print!("\n  Transaction appears incomplete ({} bytes).  Keep waiting or clear? Enter w or c: ", bytes_count);
+        let _ = std::io::stdout().flush();
+
+        let line = read_stdin_line().unwrap_or_else(|| exit_at_end_of_input_within_transaction());
+
+        match line.trim() {
+            "w" => return false,
+            "c" => return true,
+            _ => println!("\n\n  {}\n", color_out("Invalid selection, try again.", COLOR_RED))
+        }
+    }
+}
+
+// Asks the user whether input that is a complete transaction in both Base64 and Base58 was Base58.  Exits if stdin has
+// been closed.
+fn ask_is_base58() -> bool
+{
+    loop {
+        print!("\n  Input is a valid transaction in both Base64 and Base58; enter 64 or 58 for the encoding used: ");
+        let _ = std::io::stdout().flush();
+
+        let line = read_stdin_line().unwrap_or_else(|| exit_at_end_of_input_within_transaction());
+
+        match line.trim() {
+            "64" => return false,
+            "58" => return true,
+            _ => println!("\n\n  {}\n", color_out("Invalid selection, try again.", COLOR_RED))
+        }
+    }
+}
+
+// Checks that the mnemonic is a valid English BIP39 mnemonic, which catches most mistyped words.  Mnemonics are
+// entered without being shown, so errors refer to words by position rather than repeating them.
+fn validate_mnemonic(mnemonic : &str) -> Result<(), String>
+{
+    bip39::Mnemonic::parse_in_normalized(bip39::Language::English, mnemonic).map(|_| ()).map_err(|e| match e {
+        bip39::Error::BadWordCount(_) => "A BIP39 mnemonic has 12, 15, 18, 21 or 24 words".to_string(),
+
+        bip39::Error::UnknownWord(index) => format!("Word {} of the mnemonic is not a BIP39 word", index + 1),
+
+        bip39::Error::InvalidChecksum => {
+            "Mnemonic checksum is invalid; a word may be wrong or out of order".to_string()
+        },
+
+        e => format!("Invalid mnemonic: {}", e)
+    })
+}
+
+// Checks a mnemonic read where there is no one to ask whether to use it if it isn't valid BIP39, which --assume-yes
+// answers in advance.  With assume_yes, an invalid mnemonic is warned of, saying where it came from, and accepted.
+fn validate_mnemonic_unless_assumed(
+    mnemonic : &str,
+    assume_yes : bool,
+    location : &str
+) -> Result<(), String>
+{
+    match validate_mnemonic(mnemonic) {
+        Err(e) if assume_yes => {
+            print_warning(
+                "invalid-mnemonic",
+                &format!("{}, on {}; using it anyway, as --assume-yes was given.", e, location)
+            );
+            Ok(())
+        },
+        result => result
+    }
+}
+
+// Asks the user whether to use a mnemonic that isn't valid BIP39 anyway, as some tools don't use BIP39 mnemonics.
+// Exits if stdin has been closed.
+fn ask_use_invalid_mnemonic() -> bool
+{
+    loop {
+        print!("\n  Use this mnemonic anyway? Enter y or n: ");
+        let _ = std::io::stdout().flush();
+
+        let line = read_stdin_line().unwrap_or_else(|| exit_at_end_of_input());
+
+        match line.trim() {
+            "y" => return true,
+            "n" => return false,
+            _ => println!("\n\n  {}\n", color_out("Invalid selection, try again.", COLOR_RED))
+        }
+    }
+}
+
+// Asks the user whether to sign a transaction that has no instructions.  Exits if stdin has been closed.
+fn ask_sign_without_instructions() -> bool
+{
+    loop {
+        print!("\n  Sign it anyway? Enter y or n: ");
+        let _ = std::io::stdout().flush();
+
+        let line = read_stdin_line().unwrap_or_else(|| exit_at_end_of_input_within_transaction());
+
+        match line.trim() {
+            "y" => return true,
+            "n" => return false,
+            _ => println!("\n\n  {}\n", color_out("Invalid selection, try again.", COLOR_RED))
+        }
+    }
+}
+
+// Lines of stdin are read by a thread of their own, so that reading them can time out.  Every line read from stdin
+// must come through here, or lines already taken by the thread would be missed.  The thread is only started on first
+// use, so that stdin can still be read directly by the modes that never read it a line at a time.  It only reads a
+// line when one is asked for, as otherwise it would take the input typed at a password prompt, which reads the
+// terminal directly.
+struct StdinLines
+{
+    requests : std::sync::mpsc::Sender<()>,
+
+    lines : std::sync::mpsc::Receiver<String>,
+
+    // A line was asked for but not yet received, because reading it timed out
+    requested : bool
+}
+
+static STDIN_LINES : std::sync::OnceLock<std::sync::Mutex<StdinLines>> = std::sync::OnceLock::new();
+
+// Reads a line from stdin, returning None at end of input (i.e. Ctrl-D)
+fn read_stdin_line() -> Option<String>
+{
+    read_stdin_line_within(None).unwrap_or(None)
+}
+
+// Reads a line from stdin, returning None at end of input (i.e. Ctrl-D), or Err if timeout is given and no line is
+// read before it passes
+fn read_stdin_line_within(timeout : Option<std::time::Duration>) -> Result<Option<String>, ()>
+{
+    let mut stdin_lines = STDIN_LINES
+        .get_or_init(|| {
+            let (request_sender, request_receiver) = std::sync::mpsc::channel();
+            let (line_sender, line_receiver) = std::sync::mpsc::channel();
+            std::thread::spawn(move || {
+                // Dropping the line sender when the loop ends is how end of input is passed on
+                while request_receiver.recv().is_ok() {
+                    let mut line = "".to_string();
+                    match std::io::stdin().read_line(&mut line) {
+                        Ok(0) | Err(_) => break,
+                        Ok(_) => {
+                            if line_sender.send(line).is_err() {
+                                break;
+                            }
+                        },
+                    }
+                }
+            });
+            std::sync::Mutex::new(StdinLines { requests : request_sender, lines : line_receiver, requested : false })
+        })
+        .lock()
+        .unwrap();
+
+    // If the thread has ended, input has ended, which receiving finds
+    if !stdin_lines.requested {
+        let _ = stdin_lines.requests.send(());
+        stdin_lines.requested = true;
+    }
+
+    let received = match timeout {
+        Some(timeout) => stdin_lines.lines.recv_timeout(timeout),
+        None => stdin_lines.lines.recv().map_err(|_| std::sync::mpsc::RecvTimeoutError::Disconnected)
+    };
+
+    match received {
+        Ok(line) => {
+            stdin_lines.requested = false;
+            Ok(Some(line))
+        },
+        Err(std::sync::mpsc::RecvTimeoutError::Timeout) => Err(()),
+        Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => Ok(None)
+    }
+}
+
+// Ends the session, which is what end of input (i.e. Ctrl-D) does at every prompt other than the challenge password
+// prompt and part way through a transaction
+fn exit_at_end_of_input() -> !
+{
+    println!("\n");
+    std::process::exit(0);
+}
+
+// Input that ends part way through a transaction, or while a question about one is being asked, leaves that
+// transaction unsigned, which a script piping transactions in must be able to tell apart from a clean end of input
+fn exit_at_end_of_input_within_transaction() -> !
+{
+    eprintln!(
+        "\n\n  {}\n",
+        color_err("ERROR: Input ended part way through a transaction, which has not been signed.", COLOR_RED)
+    );
+    std::process::exit(-1);
+}
+
+fn print_unknown_command(command : &str)
+{
+    eprintln!("\n  {}", color_err(&format!("Unknown command: {}", command), COLOR_RED));
+}
+
+// Lets the user select one of the loaded keys and removes it, so that it can no longer be used for signing.  The
+// secret key is zeroized by ed25519_dalek when the removed keypair is dropped.
+fn remove_key(
+    keys : &mut HashMap<Pubkey, ed25519_dalek::Keypair>,
+    keys_in_order : &mut Vec<Pubkey>
+)
+{
+    if keys_in_order.is_empty() {
+        println!("\n  No keys to remove.");
+        return;
+    }
+
+    if let Some(selection) = select_loaded_key(keys_in_order) {
+        let pubkey = keys_in_order.remove(selection);
+        drop(keys.remove(&pubkey));
+        println!("\n  Removed key {}", pubkey);
+    }
+}
+
+// Prompts for and reads transactions from stdin, calling f with each one that decodes, until stdin ends.  Stops after
+// the first transaction if no_prompt is set.
+fn for_each_transaction(
+    no_prompt : bool,
+    mut f : impl FnMut(Transaction)
+)
+{
+    loop {
+        println!("\n  Enter Base64 encoded transaction:\n");
+
+        match read_input() {
+            Some(Input::Transaction(decoded_tx)) => f(decoded_tx),
+
+            Some(Input::Command(command)) => print_unknown_command(&command),
+
+            Some(Input::End) => exit_at_end_of_input(),
+
+            None => ()
+        }
+
+        // no_prompt stops after the first transaction
+        if no_prompt {
+            println!();
+            break;
+        }
+    }
+}
+
+// Prompts for and reads pairs of copies of a transaction from stdin until stdin ends, and prints a single transaction
+// with every valid signature from both copies.  Stops after the first pair if no_prompt is set.
+fn merge_transactions(options : &Options)
+{
+    let read_transaction = |which : &str| {
+        println!("\n  Enter {} Base64 encoded transaction:\n", which);
+
+        match read_input() {
+            Some(Input::Transaction(decoded_tx)) => Some(decoded_tx),
+
+            Some(Input::Command(command)) => {
+                print_unknown_command(&command);
+                None
+            },
+
+            Some(Input::End) => exit_at_end_of_input(),
+
+            None => None
+        }
+    };
+
+    loop {
+        if let Some(merged_tx) = read_transaction("first").and_then(|first_tx| {
+            read_transaction("second").and_then(|second_tx| merge_signatures(&first_tx, &second_tx))
+        }) {
+            print_transaction(&merged_tx, options);
+        }
+
+        // no_prompt stops after the first pair of transactions
+        if options.no_prompt {
+            println!();
+            break;
+        }
+    }
+}
+
+// Returns a copy of the first transaction carrying every valid signature from either transaction, or None if the
+// transactions don't have the same message, as then no signature can be valid for both
+fn merge_signatures(
+    first_tx : &Transaction,
+    second_tx : &Transaction
+) -> Option<Transaction>
+{
+    let mut message = vec![];
+    let mut second_message = vec![];
+    if let Err(e) = first_tx.message(&mut message).and_then(|_| second_tx.message(&mut second_message)) {
+        eprintln!("\n  {}", color_err(&e.to_string(), COLOR_RED));
+        return None;
+    }
+
+    if message != second_message {
+        eprintln!("\n  {}", color_err("Transactions have different messages; not merging them.", COLOR_RED));
+        return None;
+    }
+
+    let mut merged_tx = first_tx.clone();
+    merged_tx.strip_signatures();
+
+    // Signatures are verified rather than trusted, so that an invalid signature in one copy can't displace a valid one
+    // in the other
+    for slot in [first_tx, second_tx]
+        .into_iter()
+        .flat_map(|tx| tx.signed_read_write_addresses.iter().chain(tx.signed_read_only_addresses.iter()))
+    {
+        if let Some(signature) = slot.signature {
+            if merged_tx.needed_signatures().any(|pubkey| pubkey == slot.pubkey) {
+                if let Err(e) = attach_signature(&mut merged_tx, &message, &slot.pubkey, signature) {
+                    eprintln!("\n  {}", color_err(&e, COLOR_RED));
+                }
+            }
+        }
+    }
+
+    println!(
+        "\n  Merged transaction has {} of {} required signatures.",
+        merged_tx.present_signatures_count(),
+        merged_tx.required_signatures_count()
+    );
+
+    Some(merged_tx)
+}
+
+// Reads transactions described in JSON, in the form written by --decode-json, from stdin until it is closed, and
+// writes the Base64 encoding of each.  Exits with an error status if any transaction could not be encoded.
+fn encode_json_transactions(options : &Options)
+{
+    let mut failed = false;
+
+    for value in serde_json::Deserializer::from_reader(std::io::stdin()).into_iter::<serde_json::Value>() {
+        let encoded = value.map_err(|e| format!("Invalid JSON: {}", e)).and_then(|value| {
+            let tx = Transaction::from_json(&value)?;
+            let mut bytes = vec![];
+            tx.encode(&mut bytes).map_err(|e| e.to_string())?;
+            // Decoding the encoded transaction checks it against all of the same limits that are applied to
+            // transactions read in for signing
+            Transaction::decode(&mut std::io::Cursor::new(&bytes))
+                .map_err(|e| format!("Invalid transaction: {}", e))?;
+            if bytes.len() > (MAXIMUM_TRANSACTION_BYTES as usize) {
+                return Err(format!(
+                    "Transaction too large: expected at most {} bytes, got {}",
+                    MAXIMUM_TRANSACTION_BYTES,
+                    bytes.len()
+                ));
+            }
+            Ok(bytes)
+        });
+
+        match encoded {
+            Ok(bytes) => {
+                println!("\n  Encoded transaction:\n");
+                print_base64(&bytes, options);
+            },
+
+            Err(e) => {
+                eprintln!("\n  {}", color_err(&e, COLOR_RED));
+                failed = true;
+                // The rest of the input can't be read past invalid JSON
+                if e.starts_with("Invalid JSON") {
+                    break;
+                }
+            }
+        }
+    }
+
+    println!();
+
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+// Runs checks of encoding, decoding and signing on transactions built from keys that are never used for anything else,
+// for --self-test, printing PASS or FAIL for each.  Returns true if every check passed.
+fn self_test() -> bool
+{
+    let keypairs : Vec<ed25519_dalek::Keypair> =
+        (1..=2).map(|seed| keypair_from_seed(&sha256(&[b"solsign self-test", &[seed]]).0).unwrap()).collect();
+    let pubkeys : Vec<Pubkey> = keypairs.iter().map(|keypair| Pubkey(keypair.public.to_bytes())).collect();
+
+    let destination = Address(sha256(&[b"solsign self-test destination"]).0);
+
+    // A System transfer of 1000 lamports, needing a signature from each of the keys
+    let tx = Transaction {
+        signed_read_write_addresses : pubkeys
+            .iter()
+            .map(|pubkey| PubkeyWithSignature { pubkey : pubkey.clone(), signature : None })
+            .collect(),
+        signed_read_only_addresses : vec![],
+        unsigned_read_write_addresses : vec![destination.clone()],
+        unsigned_read_only_addresses : vec![SYSTEM_PROGRAM_ADDRESS],
+        recent_blockhash : Some(sha256(&[b"solsign self-test blockhash"])),
+        instructions : vec![Instruction {
+            program_address : SYSTEM_PROGRAM_ADDRESS,
+            addresses : vec![(Address(pubkeys[0].0), true, true), (destination.clone(), false, true)],
+            data : [&SYSTEM_TRANSFER.to_le_bytes()[..], &1000_u64.to_le_bytes()].concat()
+        }]
+    };
+
+    let encode = |tx : &Transaction| {
+        let mut bytes = vec![];
+        tx.encode(&mut bytes).map(|_| bytes).map_err(|e| e.to_string())
+    };
+
+    let mut message = vec![];
+    let _ = tx.message(&mut message);
+
+    type Check<'a> = Box<dyn Fn() -> Result<(), String> + 'a>;
+
+    let checks : Vec<(&str, Check)> = vec![
+        (
+            "Encoded transaction decodes to the same bytes",
+            Box::new(|| {
+                let bytes = encode(&tx)?;
+                let decoded = Transaction::decode(&mut bytes.as_slice()).map_err(|e| e.to_string())?;
+                if encode(&decoded)? == bytes {
+                    Ok(())
+                }
+                else {
+                    Err("re-encoded bytes differ".to_string())
+                }
+            })
+        ),
+        (
+            "Transfer instruction is described",
+            Box::new(|| match tx.instructions[0].decode_known() {
+                Some(KnownInstruction::Transfer(1000, address)) if address == destination => Ok(()),
+                _ => Err("not described as a transfer of 1000 lamports to its destination".to_string())
+            })
+        ),
+        (
+            "Both signers are needed",
+            Box::new(|| {
+                if tx.needed_signatures().collect::<Vec<Pubkey>>().len() == 2 {
+                    Ok(())
+                }
+                else {
+                    Err("wrong signers".to_string())
+                }
+            })
+        ),
+        (
+            "Signing with one key leaves only the other needed",
+            Box::new(|| {
+                let mut tx = tx.clone();
+                tx.sign(&pubkeys[0], keypairs[0].sign(&message)).map_err(|e| e.to_string())?;
+                if tx.needed_signatures().collect::<Vec<Pubkey>>() == vec![pubkeys[1].clone()] {
+                    Ok(())
+                }
+                else {
+                    Err("wrong signers still needed".to_string())
+                }
+            })
+        ),
+        (
+            "Signatures survive encoding and verify",
+            Box::new(|| {
+                let mut tx = tx.clone();
+                for (pubkey, keypair) in pubkeys.iter().zip(&keypairs) {
+                    tx.sign(pubkey, keypair.sign(&message)).map_err(|e| e.to_string())?;
+                }
+                let decoded = Transaction::decode(&mut encode(&tx)?.as_slice()).map_err(|e| e.to_string())?;
+                for (slot, keypair) in decoded.signed_read_write_addresses.iter().zip(&keypairs) {
+                    let signature = slot.signature.ok_or("signature missing after decoding")?;
+                    keypair.public.verify_strict(&message, &signature).map_err(|e| e.to_string())?;
+                }
+                Ok(())
+            })
+        ),
+        (
+            "Signature does not verify a changed message",
+            Box::new(|| {
+                let mut changed = message.clone();
+                *changed.last_mut().ok_or("empty message")? ^= 1;
+                match keypairs[0].public.verify_strict(&changed, &keypairs[0].sign(&message)) {
+                    Ok(()) => Err("signature verified".to_string()),
+                    Err(_) => Ok(())
+                }
+            })
+        ),
+        (
+            "JSON decodes to the same transaction",
+            Box::new(|| {
+                if encode(&Transaction::from_json(&tx.to_json())?)? == encode(&tx)? {
+                    Ok(())
+                }
+                else {
+                    Err("encoded bytes differ".to_string())
+                }
+            })
+        )
+    ];
+
+    println!("\n  Self test:\n");
+
+    let mut passed = true;
+
+    for (description, check) in checks {
+        match check() {
+            Ok(()) => println!("    {}  {}", color_out("PASS", COLOR_GREEN), description),
+            Err(e) => {
+                println!("    {}  {}: {}", color_out("FAIL", COLOR_RED), description, e);
+                passed = false;
+            }
+        }
+    }
+
+    println!();
+
+    passed
+}
+
+// Prints every address of the transaction, one per line, with its index and whether it is a signer and is writable
+fn print_addresses(tx : &Transaction)
+{
+    println!("\n  Addresses:\n");
+
+    // An address may be listed more than once, so each is given the index of where it is listed, which looking it up
+    // would not give for any but its first listing
+    for (index, (address, is_signer, is_writable)) in addresses_with_roles(tx).enumerate() {
+        println!(
+            "    {:>3}  {:<44}  {:<8}  {}",
+            index,
+            address.to_string(),
+            if is_signer { "signer" } else { "unsigned" },
+            if is_writable { "writable" } else { "readonly" }
+        );
+    }
+}
+
+// Prints every address of the transaction as CSV, for --addresses-csv, with a header row
+fn print_addresses_csv(tx : &Transaction)
+{
+    println!("address,is_signer,is_writable,index");
+
+    // As for --addresses, each is given the index of where it is listed
+    for (index, (address, is_signer, is_writable)) in addresses_with_roles(tx).enumerate() {
+        println!("{},{},{},{}", address, is_signer, is_writable, index);
+    }
+}
+
+// Every address of the transaction in order, with whether it is a signer and whether it is writable
+fn addresses_with_roles(tx : &Transaction) -> impl Iterator<Item = (Address, bool, bool)> + '_
+{
+    tx.signed_read_write_addresses
+        .iter()
+        .map(|s| (Address(s.pubkey.0), true, true))
+        .chain(tx.signed_read_only_addresses.iter().map(|s| (Address(s.pubkey.0), true, false)))
+        .chain(tx.unsigned_read_write_addresses.iter().map(|a| (a.clone(), false, true)))
+        .chain(tx.unsigned_read_only_addresses.iter().map(|a| (a.clone(), false, false)))
+}
+
+// Prompts for and reads the Base64 encoded data of address lookup table accounts from stdin, for --inspect, and prints
+// the addresses that each holds along with the index by which a v0 transaction refers to each.  The data of each
+// table ends with an empty line, or the end of input.  Stops after the first table if no_prompt is set.
+fn inspect_lookup_tables(no_prompt : bool)
+{
+    loop {
+        println!("\n  Enter Base64 encoded lookup table account data, followed by an empty line:\n");
+
+        let (encoded, ended) = read_until_empty_line();
+
+        if encoded.is_empty() {
+            exit_at_end_of_input();
+        }
+
+        let table = base64::decode(&encoded)
+            .map_err(|e| format!("Invalid Base64: {}", e))
+            .and_then(|data| LookupTable::decode(&data));
+
+        match table {
+            Ok(table) => {
+                println!("\n  Lookup table:\n");
+                println!(
+                    "    Authority            : {}",
+                    table.authority.map_or("None (frozen)".to_string(), |authority| authority.to_string())
+                );
+                if table.deactivation_slot == u64::MAX {
+                    println!("    Status               : Active");
+                }
+                else {
+                    println!("    Status               : Deactivated at slot {}", table.deactivation_slot);
+                }
+                println!("    Last extended slot   : {}", table.last_extended_slot);
+                println!(
+                    "    Addresses            : {}/{}\n",
+                    table.addresses.len(),
+                    MAXIMUM_LOOKUP_TABLE_ADDRESSES_COUNT
+                );
+                for (index, address) in table.addresses.iter().enumerate() {
+                    println!("    {:>3}  {}", index, address);
+                }
+            },
+
+            Err(e) => eprintln!("\n  {}", color_err(&format!("Invalid lookup table: {}", e), COLOR_RED))
+        }
+
+        // no_prompt stops after the first table
+        if no_prompt || ended {
+            println!();
+            break;
+        }
+    }
+}
+
+// Reads lines from stdin until an empty line that follows at least one that isn't empty, or until stdin ends, returning
+// the lines joined together with surrounding whitespace removed, and whether stdin ended
+fn read_until_empty_line() -> (String, bool)
+{
+    let mut text = String::new();
+
+    loop {
+        match read_stdin_line() {
+            Some(line) if !line.trim().is_empty() => text.push_str(line.trim()),
+            Some(_) if text.is_empty() => (),
+            Some(_) => return (text, false),
+            None => return (text, true)
+        }
+    }
+}
+
+// Prompts for and reads Base64 encoded transactions from stdin, for --dump-raw, and prints a hex dump of each, showing
+// the field that each byte belongs to.  Transactions are read whole, up to an empty line, rather than as they decode,
+// so that those that fail to decode can still be dumped.  Stops after the first transaction if no_prompt is set.
+fn dump_raw_transactions(no_prompt : bool)
+{
+    loop {
+        println!("\n  Enter Base64 encoded transaction, followed by an empty line:\n");
+
+        let (encoded, ended) = read_until_empty_line();
+
+        if encoded.is_empty() {
+            exit_at_end_of_input();
+        }
+
+        match base64::decode(&encoded) {
+            Ok(bytes) => print_raw_dump(&bytes),
+
+            Err(e) => eprintln!("\n  {}", color_err(&format!("Invalid Base64 input: {}", e), COLOR_RED))
+        }
+
+        // no_prompt stops after the first transaction
+        if no_prompt || ended {
+            println!();
+            break;
+        }
+    }
+}
+
+// Prints the bytes of an encoded transaction 16 to a line, each field of the transaction starting a new line that is
+// headed by the offset and name of the field.  Bytes after the point where decoding stopped are shown last.
+fn print_raw_dump(bytes : &[u8])
+{
+    let (result, fields, read_count) = Transaction::decode_fields(bytes);
+
+    println!("\n  Raw transaction ({} bytes):\n", bytes.len());
+
+    let print_field = |name : &str, start : usize, field_bytes : &[u8]| {
+        if field_bytes.is_empty() {
+            println!("    {:>5}  {:<30}", start, name);
+        }
+        for (i, chunk) in field_bytes.chunks(16).enumerate() {
+            println!("    {:>5}  {:<30}  {}", start + (i * 16), if i == 0 { name } else { "" }, hex(chunk));
+        }
+    };
+
+    // Each field ends where the next starts, and the last where decoding stopped
+    let ends = fields.iter().skip(1).map(|(_, start)| *start).chain(std::iter::once(read_count));
+
+    for ((name, start), end) in fields.iter().zip(ends) {
+        print_field(name, *start, &bytes[*start..end]);
+    }
+
+    if read_count < bytes.len() {
+        print_field(if result.is_ok() { "Trailing bytes" } else { "Not read" }, read_count, &bytes[read_count..]);
+    }
+
+    if let Err(e) = result {
+        eprintln!("\n  {}", color_err(&format!("Invalid transaction: {}", e), COLOR_RED));
+    }
+}
+
+// Prints every instruction of the transaction, one per line, with its index, program, and how many accounts and bytes
+// of data it has
+fn print_instructions_list(tx : &Transaction)
+{
+    println!("\n  Instructions:\n");
+
+    for (i, instruction) in tx.instructions.iter().enumerate() {
+        println!(
+            "    {:>3}  {:<44}  {:>3} accounts  {:>4} bytes",
+            i,
+            instruction.program_address.to_string(),
+            instruction.addresses.len(),
+            instruction.data.len()
+        );
+    }
+}
+
+// Signs a transaction with every loaded key that it needs, and prints the signed transaction along with either its
+// signature, if it is now completely signed, or the pubkeys that must still sign it.  read_messages holds the hashes
+// of the messages of the transactions read earlier in the session.
+fn sign_transaction(
+    mut decoded_tx : Transaction,
+    keys : &HashMap<Pubkey, ed25519_dalek::Keypair>,
+    options : &Options,
+    password : &str,
+    used_keys : &mut HashSet<Pubkey>,
+    read_messages : &mut HashSet<Sha256Digest>
+)
+{
+    // Get the transaction to sign -- everything except the signatures
+    let mut message = vec![];
+    decoded_tx.message(&mut message).unwrap_or_else(|e| {
+        eprintln!("\n{}\n", color_err(&e.to_string(), COLOR_RED));
+        std::process::exit(-1);
+    });
+
+    // Whether the account that pays the transaction's fees is one of the user's own
+    let fee_payer_held = decoded_tx.fee_payer().is_some_and(|fee_payer| keys.contains_key(&fee_payer.pubkey));
+
+    // With --binary or --signature-only, stdout carries only signed transactions or their signatures, so everything for
+    // the user goes to stderr instead
+    let mut display : Box<dyn Write> = if options.binary || options.signature_only {
+        Box::new(std::io::stderr())
+    }
+    else {
+        Box::new(std::io::stdout())
+    };
+
+    let _ = print_summary(&mut display, &decoded_tx, &options.program_filter, options.sol, fee_payer_held);
+
+    if options.explain {
+        let _ = print_signer_reasons(&mut display, &decoded_tx);
+    }
+
+    // Signatures don't change the message, so a transaction read again, signed or not, has the same message.  When
+    // many transactions are pasted or piped in, this is most likely one copied twice by mistake.
+    if !read_messages.insert(sha256(&[&message])) {
+        print_warning("duplicate-transaction", "An identical transaction was already read in this session.");
+    }
+
+    // The runtime refuses a transaction that lists an address more than once, even with different roles, such as a
+    // signer that is also listed unsigned
+    let addresses : Vec<(Address, bool, bool)> = addresses_with_roles(&decoded_tx).collect();
+    let mut duplicates = Vec::<&Address>::new();
+    for (index, (address, _, _)) in addresses.iter().enumerate() {
+        if addresses[..index].iter().any(|(earlier, _, _)| earlier == address) && !duplicates.contains(&address) {
+            duplicates.push(address);
+        }
+    }
+    for address in duplicates {
+        print_warning(
+            "duplicate-account",
+            &format!("Address {} is listed more than once, so the transaction will fail.", address)
+        );
+    }
+
+    // A signature already in the transaction that doesn't sign its message will make it fail, unless it's replaced
+    // by one of the signatures about to be made
+    for slot in decoded_tx.signed_read_write_addresses.iter().chain(decoded_tx.signed_read_only_addresses.iter()) {
+        if let Some(signature) = slot.signature {
+            if ed25519_dalek::PublicKey::from_bytes(&slot.pubkey.0)
+                .and_then(|public_key| public_key.verify_strict(&message, &signature))
+                .is_err()
+            {
+                print_warning(
+                    "invalid-signature",
+                    &format!("The signature of {} does not sign this transaction.", slot.pubkey)
+                );
+            }
+        }
+    }
+
+    // The network drops transactions that are too large without executing them, but signing one does no harm, so it's
+    // only pointed out
+    let mut encoded_tx = vec![];
+    if decoded_tx.encode(&mut encoded_tx).is_ok() && (encoded_tx.len() > (MAXIMUM_TRANSACTION_BYTES as usize)) {
+        print_warning(
+            "oversize",
+            &format!(
+                "Transaction is {} bytes, more than the {} that the network accepts.",
+                encoded_tx.len(),
+                MAXIMUM_TRANSACTION_BYTES
+            )
+        );
+    }
+
+    // A transaction with an all zero recent blockhash will never be accepted by the network, so signing it
+    // produces a useless signature
+    if decoded_tx.recent_blockhash.is_none() && !decoded_tx.is_durable_nonce() {
+        print_warning("empty-blockhash", "Transaction has an empty recent blockhash and will never execute.");
+        if !options.allow_empty_blockhash {
+            eprintln!("\n  Not signing it; use --allow-empty-blockhash to sign it anyway.\n");
+            return;
+        }
+    }
+
+    // A transaction without instructions does nothing, and is most likely the result of a mistake in whatever built
+    // it, so it is only signed if the user confirms that it should be, or has confirmed in advance with --assume-yes.
+    // There's no one to ask with --no-prompt, --binary or --concatenated, so it isn't signed at all then otherwise.
+    if decoded_tx.instructions.is_empty() {
+        print_warning("no-instructions", "Transaction has no instructions and will do nothing if executed.");
+        if options.assume_yes {
+            eprintln!("\n  Signing it anyway, as --assume-yes was given.");
+        }
+        else if options.no_prompt || options.binary || options.concatenated || !ask_sign_without_instructions() {
+            eprintln!("\n  {}\n", color_err("Not signing it.", COLOR_RED));
+            return;
+        }
+    }
+
+    // Every signature adds to the fee, so a transaction requiring an unexpectedly large number of them may be an
+    // attempt to waste the fee payer's funds
+    if let Some(max_signers) = options.max_signers {
+        if (decoded_tx.required_signatures_count() as u64) > max_signers {
+            let e = format!(
+                "Transaction requires {} signatures, more than --max-signers {}; not signing it.",
+                decoded_tx.required_signatures_count(),
+                max_signers
+            );
+            eprintln!("\n  {}\n", color_err(&e, COLOR_RED));
+            return;
+        }
+    }
+
+    if let Err(e) = check_transfers(&decoded_tx, options) {
+        eprintln!("\n  {}\n", color_err(&format!("{}; not signing it.", e), COLOR_RED));
+        return;
+    }
+
+    // An external policy command gets the final say over whether the transaction may be signed
+    if let Some(policy_command) = &options.policy_command {
+        if let Err(e) = check_policy(policy_command, &decoded_tx) {
+            eprintln!("\n  {}\n", color_err(&format!("{}; not signing it.", e), COLOR_RED));
+            return;
+        }
+    }
+
+    let present_before_count = decoded_tx.present_signatures_count();
+
+    // Attach any signatures produced elsewhere that are still needed by this transaction.  These are verified rather
+    // than made with loaded keys, so there is no need to challenge for them.
+    let needed : Vec<Pubkey> = decoded_tx.needed_signatures().collect();
+    for (pubkey, signature) in options.signatures.iter().filter(|(pubkey, _)| needed.contains(pubkey)) {
+        if let Err(e) = attach_signature(&mut decoded_tx, &message, pubkey, *signature) {
+            eprintln!("\n  {}", color_err(&e, COLOR_RED));
+        }
+    }
+
+    // Only keys allowed by --signer are used, so that other loaded keys leave their signatures missing
+    let keys : HashMap<&Pubkey, &ed25519_dalek::Keypair> =
+        keys.iter().filter(|(pubkey, _)| options.signers.is_empty() || options.signers.contains(pubkey)).collect();
+
+    // Let the user know how many of the needed signatures can be made before asking them for the password
+    let needed : Vec<Pubkey> = decoded_tx.needed_signatures().collect();
+    let providable_count = needed.iter().filter(|pubkey| keys.contains_key(pubkey)).count();
+    if !needed.is_empty() {
+        let _ = writeln!(
+            display,
+            "\n  This session can provide {} of {} required signatures.",
+            providable_count,
+            needed.len()
+        );
+    }
+
+    // If none of them can be made, there's nothing to challenge for; go straight to output of the transaction
+    if (providable_count > 0) &&
+        !challenge_password(password, &mut || {
+            let _ = print_summary(&mut display, &decoded_tx, &options.program_filter, options.sol, fee_payer_held);
+        })
+    {
+        return;
+    }
+
+    // For every signature incomplete within the transaction, make that signature if we have the key.  The signatures
+    // made are then applied in a single pass.
+    let signatures : Vec<(Pubkey, ed25519_dalek::Signature)> = needed
+        .into_iter()
+        .filter_map(|pubkey| keys.get(&pubkey).map(|keypair| (pubkey, keypair.sign(&message))))
+        .collect();
+    decoded_tx.sign_all(&signatures);
+    used_keys.extend(signatures.into_iter().map(|(pubkey, _)| pubkey));
+
+    if options.count {
+        let present_after_count = decoded_tx.present_signatures_count();
+        let _ = writeln!(
+            display,
+            "\n  Signatures: {} added, {} already present, {} missing",
+            present_after_count - present_before_count,
+            present_before_count,
+            decoded_tx.required_signatures_count() - present_after_count
+        );
+    }
+
+    // A partially signed transaction is of no use to a pipeline that submits whatever it is given
+    if options.require_complete {
+        let missing : Vec<Pubkey> = decoded_tx.needed_signatures_ordered().collect();
+        if !missing.is_empty() {
+            eprintln!(
+                "\n  {}\n",
+                color_err("ERROR: Transaction is not completely signed; these pubkeys must still sign it:", COLOR_RED)
+            );
+            missing.iter().for_each(|pubkey| eprintln!("    {}", color_err(&pubkey.to_string(), COLOR_RED)));
+            eprintln!();
+            std::process::exit(-1);
+        }
+    }
+
+    if options.binary {
+        write_binary_transaction(&decoded_tx, options.length_prefixed);
+    }
+    else {
+        print_transaction(&decoded_tx, options);
+    }
+}
+
+// Writes the transaction to stdout in its binary wire format, for --binary.  The pubkeys that must still sign it are
+// written to stderr in the same line oriented format as with --no-prompt.  If length_prefixed is set, the transaction
+// is preceded by its length as a little-endian u32, for --length-prefixed.
+fn write_binary_transaction(
+    tx : &Transaction,
+    length_prefixed : bool
+)
+{
+    let mut encoded_tx = vec![];
+    if let Err(e) = tx.encode(&mut encoded_tx) {
+        eprintln!("\n{}\n", color_err(&e.to_string(), COLOR_RED));
+        return;
+    }
+
+    if length_prefixed {
+        // A transaction can't be anywhere near 4 GiB, so its length always fits
+        encoded_tx.splice(0..0, (encoded_tx.len() as u32).to_le_bytes());
+    }
+
+    let mut stdout = std::io::stdout();
+    if let Err(e) = stdout.write_all(&encoded_tx).and_then(|_| stdout.flush()) {
+        eprintln!("\n  {}\n", color_err(&format!("Failed to write transaction: {}", e), COLOR_RED));
+        std::process::exit(-1);
+    }
+
+    let unsigned : Vec<Pubkey> = tx.needed_signatures_ordered().collect();
+    if !unsigned.is_empty() {
+        eprintln!("missing-signers: {}", unsigned.len());
+        unsigned.iter().for_each(|pubkey| eprintln!("missing-signer: {}", pubkey));
+    }
+}
+
+// Reads transactions in their binary wire format from input, for --binary, signing each, until input ends.  The wire
+// format gives the length of everything within it, so transactions can be read one after another with no framing.
+// Stops after the first transaction if no_prompt is set.
+fn sign_binary_transactions(
+    input : &mut impl BufRead,
+    keys : &HashMap<Pubkey, ed25519_dalek::Keypair>,
+    options : &Options,
+    password : &str
+)
+{
+    let mut used_keys = HashSet::<Pubkey>::new();
+
+    let mut read_messages = HashSet::<Sha256Digest>::new();
+
+    // Input that ends between transactions is the end of the session, and not an error
+    while input.fill_buf().map(|buf| !buf.is_empty()).unwrap_or(false) {
+        match Transaction::decode(input) {
+            Ok(decoded_tx) => sign_transaction(decoded_tx, keys, options, password, &mut used_keys, &mut read_messages),
+
+            // There is no way to find the start of the next transaction after one that could not be decoded
+            Err(e) => {
+                eprintln!("\n  {}\n", color_err(&format!("Invalid transaction: {}", e), COLOR_RED));
+                std::process::exit(-1);
+            }
+        }
+
+        if options.no_prompt {
+            break;
+        }
+    }
+}
+
+// Reads all of stdin as Base64 encoded transactions with nothing separating one from the next, for --concatenated,
+// and signs each.  Transaction::decode reads exactly as many bytes as a transaction holds, so the decoded bytes are
+// split into transactions the same way as with --binary.
+fn sign_concatenated_transactions(
+    keys : &HashMap<Pubkey, ed25519_dalek::Keypair>,
+    options : &Options,
+    password : &str
+)
+{
+    let mut text = "".to_string();
+
+    while let Some(line) = read_stdin_line() {
+        text.extend(line.chars().filter(|c| !is_ignored_in_input(*c)));
+    }
+
+    let bytes = base64::decode(&text).unwrap_or_else(|e| {
+        eprintln!("\n  {}\n", color_err(&format!("Invalid Base64 input: {}", e), COLOR_RED));
+        std::process::exit(-1);
+    });
+
+    sign_binary_transactions(&mut bytes.as_slice(), keys, options, password);
+}
+
+// Prints the transaction: if it is completely signed, the transaction and its signature, otherwise, the pubkeys that
+// must still sign it and the partially signed transaction, ready for further signing
+fn print_transaction(
+    tx : &Transaction,
+    options : &Options
+)
+{
+    let unsigned : Vec<String> = tx.needed_signatures_ordered().map(|pubkey| pubkey.to_string()).collect();
+
+    let format_signature = |signature : ed25519_dalek::Signature| match options.signature_format {
+        SignatureFormat::Base58 => bs58::encode(signature.to_bytes()).into_string(),
+        SignatureFormat::Base64 => base64::encode(signature.to_bytes()),
+        SignatureFormat::Hex => hex(&signature.to_bytes())
+    };
+
+    // For --signature-only, a completely signed transaction is nothing but its signature
+    if options.signature_only {
+        match tx.fee_payer().and_then(|f| f.signature) {
+            Some(signature) if unsigned.is_empty() => println!("{}", format_signature(signature)),
+            _ => {
+                eprintln!("missing-signers: {}", unsigned.len());
+                unsigned.iter().for_each(|pubkey| eprintln!("missing-signer: {}", pubkey));
+            }
+        }
+        return;
+    }
+
+    let mut encoded_tx = vec![];
+    match tx.encode(&mut encoded_tx) {
+        Ok(()) => {
+            let print_encoded_tx = || {
+                if options.cli_json {
+                    println!("{}", serde_json::to_string_pretty(&tx.to_cli_json()).unwrap_or_default());
+                }
+                else {
+                    print_base64(&encoded_tx, options);
+                }
+            };
+
+            // Now, if the transaction is completely signed, emit the signature
+            if unsigned.is_empty() {
+                // With no unsigned addresses, the fee payer signature can only be missing if there is no fee
+                // payer at all
+                match tx.fee_payer().and_then(|f| f.signature) {
+                    Some(signature) => {
+                        println!("\n  {}\n", color_out("Transaction is complete:", COLOR_GREEN));
+                        print_encoded_tx();
+                        println!("\n  Signature:\n\n   {}", color_out(&format_signature(signature), COLOR_GREEN));
+                    },
+                    None => {
+                        eprintln!("\n  {}\n", color_err("Transaction has no fee payer, cannot sign it.", COLOR_RED))
+                    }
+                }
+            }
+            // Else, emit the partially signed tx
+            else {
+                println!("\n  {}", color_out("Pubkeys still needed to sign:", COLOR_YELLOW));
+                unsigned.iter().for_each(|pubkey| println!("\n    {}", color_out(pubkey, COLOR_YELLOW)));
+                println!("\n  Partially signed transaction:\n");
+                print_encoded_tx();
+                // Scripts driving solsign with --no-prompt get the missing signers on stderr in a stable,
+                // line oriented format
+                if options.no_prompt {
+                    eprintln!("missing-signers: {}", unsigned.len());
+                    unsigned.iter().for_each(|pubkey| eprintln!("missing-signer: {}", pubkey));
+                }
+            }
+        },
+        Err(e) => eprintln!("\n{}\n", color_err(&e.to_string(), COLOR_RED))
+    }
+}
+
+// Runs the policy command through the shell with the transaction, as written by --decode-json, on its stdin.  The
+// transaction is approved only if the command exits successfully.  The command's stderr is left connected to
+// solsign's so that the user sees why a transaction was rejected, and its stdout goes to stderr too, so that it
+// can't be mistaken for solsign's own output.
+fn check_policy(
+    policy_command : &str,
+    tx : &Transaction
+) -> Result<(), String>
+{
+    let mut child = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(policy_command)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::io::stderr())
+        .spawn()
+        .map_err(|e| format!("Failed to run policy command: {}", e))?;
+
+    // A command that exits without reading its input is not an error here; its exit status decides
+    if let Some(mut stdin) = child.stdin.take() {
+        let _ = writeln!(stdin, "{}", serde_json::to_string_pretty(&tx.to_json()).unwrap_or_default());
+    }
+
+    let status = child.wait().map_err(|e| format!("Failed to run policy command: {}", e))?;
+
+    if status.success() {
+        Ok(())
+    }
+    else {
+        Err(format!("Policy command rejected the transaction ({})", status))
+    }
+}
+
+// Parses the value of an option which gives an amount, exiting if it isn't one
+fn parse_amount_arg(
+    option : &str,
+    value : Option<String>
+) -> u64
+{
+    let value = value.unwrap_or_default();
+
+    value.parse::<u64>().unwrap_or_else(|_| {
+        eprintln!("\n  {}\n", color_err(&format!("Invalid {} {}", option, value), COLOR_RED));
+        std::process::exit(1);
+    })
+}
+
+// Returns the value of an option which must be given one, exiting if it is missing or empty, rather than going on as if
+// the option had not been given
+fn required_arg(
+    option : &str,
+    value : Option<String>
+) -> String
+{
+    value.filter(|value| !value.is_empty()).unwrap_or_else(|| {
+        eprintln!("\n  {}\n", color_err(&format!("Missing value for {}", option), COLOR_RED));
+        std::process::exit(1);
+    })
+}
+
+// Decodes a Base58 encoded pubkey, which must be exactly 32 bytes.  This is what parsing a Pubkey or an Address does.
+fn decode_base58_pubkey(s : &str) -> Result<[u8; 32], String>
+{
+    let bytes = bs58::decode(s).into_vec().map_err(|e| format!("invalid Base58: {}", e))?;
+
+    <[u8; 32]>::try_from(bytes).map_err(|bytes| format!("expected 32 bytes, got {}", bytes.len()))
+}
+
+// Checks the transfers made by the transaction against the limits given on the command line, returning a description
+// of the first instruction that breaks them.  The limits apply to the total moved by the whole transaction, so that
+// splitting a transfer across instructions doesn't get around them.  Token amounts are totalled for each source
+// account, since amounts of different tokens can't be added together.
+fn check_transfers(
+    tx : &Transaction,
+    options : &Options
+) -> Result<(), String>
+{
+    if options.allowed_destinations.is_empty() && options.max_lamports.is_none() && options.max_token_amount.is_none() {
+        return Ok(());
+    }
+
+    let mut total_lamports = 0_u64;
+
+    let mut token_totals : Vec<(Address, u64)> = vec![];
+
+    for (i, instruction) in tx.instructions.iter().enumerate() {
+        // Besides funds going to the destination, an approved delegate, a closed account's destination and a new
+        // authority all gain control of funds, so each must be an allowed destination too
+        let (lamports, token_amount, destination) = match instruction.decode_known() {
+            Some(KnownInstruction::Transfer(lamports, destination)) |
+            Some(KnownInstruction::CreateAccount(lamports, destination)) |
+            Some(KnownInstruction::WithdrawNonceAccount(lamports, destination)) => (lamports, 0, Some(destination)),
+
+            Some(KnownInstruction::TokenTransfer(amount, destination)) |
+            Some(KnownInstruction::TokenApprove(amount, destination)) => (0, amount, Some(destination)),
+
+            Some(KnownInstruction::TokenCloseAccount(_, destination)) => (0, 0, Some(destination)),
+
+            Some(KnownInstruction::TokenSetAuthority(authority)) => (0, 0, authority),
+
+            Some(_) => continue,
+
+            // Any other instruction of these programs might move funds in a way that can't be checked
+            None if [SYSTEM_PROGRAM_ADDRESS, TOKEN_PROGRAM_ADDRESS, TOKEN_2022_PROGRAM_ADDRESS]
+                .contains(&instruction.program_address) =>
+            {
+                return Err(format!("Instruction {} is not one that solsign can check against the limits", i));
+            },
+
+            None => continue
+        };
+
+        total_lamports = total_lamports.saturating_add(lamports);
+
+        if let Some(maximum) = options.max_lamports.filter(|maximum| total_lamports > *maximum) {
+            return Err(format!(
+                "Instructions up to {} move {} lamports in total, which is more than the limit of {}",
+                i, total_lamports, maximum
+            ));
+        }
+
+        // The source account is the first account of each token instruction that moves an amount
+        if token_amount > 0 {
+            let source = &instruction.addresses[0].0;
+            let total = match token_totals.iter_mut().find(|(address, _)| address == source) {
+                Some((_, total)) => {
+                    *total = total.saturating_add(token_amount);
+                    *total
+                },
+                None => {
+                    token_totals.push((source.clone(), token_amount));
+                    token_amount
+                }
+            };
+
+            if let Some(maximum) = options.max_token_amount.filter(|maximum| total > *maximum) {
+                return Err(format!(
+                    "Instructions up to {} move {} token units from {} in total, which is more than the limit of {}",
+                    i, total, source, maximum
+                ));
+            }
+        }
+
+        if let Some(destination) = destination {
+            if !options.allowed_destinations.is_empty() && !options.allowed_destinations.contains(&destination) {
+                return Err(format!(
+                    "Instruction {} gives funds or control of them to {}, which is not an allowed destination",
+                    i, destination
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Parses a PUBKEY=SIGNATURE pair, both Base58 encoded
+fn parse_signature_arg(value : &str) -> Result<(Pubkey, ed25519_dalek::Signature), String>
+{
+    let (pubkey, signature) = value.split_once('=').ok_or("expected PUBKEY=SIGNATURE".to_string())?;
+
+    let pubkey = pubkey.parse::<Pubkey>().map_err(|e| format!("invalid pubkey {}: {}", pubkey, e))?;
+
+    let signature = bs58::decode(signature)
+        .into_vec()
+        .ok()
+        .and_then(|v| ed25519_dalek::Signature::from_bytes(&v).ok())
+        .ok_or(format!("invalid signature {}", signature))?;
+
+    Ok((pubkey, signature))
+}
+
+// Attaches a signature that was produced elsewhere to the transaction, but only if it is a valid signature of the
+// message by pubkey, so that a signature for some other message can't be attached by mistake
+fn attach_signature(
+    tx : &mut Transaction,
+    message : &[u8],
+    pubkey : &Pubkey,
+    signature : ed25519_dalek::Signature
+) -> Result<(), String>
+{
+    ed25519_dalek::PublicKey::from_bytes(&pubkey.0)
+        .and_then(|public_key| public_key.verify_strict(message, &signature))
+        .map_err(|_| format!("Signature provided for {} does not sign this transaction; not attaching it", pubkey))?;
+
+    tx.sign(pubkey, signature).map_err(|e| e.to_string())
+}
+
+// Writes a summary of the transaction to out, so that the user knows what they are about to sign.  If program_filter
+// is not empty, only the instructions of the programs within it are written in full.  If sol is set, transfer amounts
+// are shown in SOL and whole tokens.  fee_payer_held says whether the fee payer is one of the loaded keys.
+fn print_summary(
+    out : &mut dyn Write,
+    tx : &Transaction,
+    program_filter : &[Address],
+    sol : bool,
+    fee_payer_held : bool
+) -> std::io::Result<()>
+{
+    let signatures_count = tx.required_signatures_count();
+
+    writeln!(out, "\n  Transaction summary:\n")?;
+
+    // The fee payer's funds pay for the transaction whatever it does, so that is shown before anything else
+    if let Some(fee_payer) = tx.fee_payer() {
+        writeln!(
+            out,
+            "    Fee payer            : {} ({})",
+            fee_payer.pubkey,
+            if fee_payer_held { "held" } else { "not held" }
+        )?;
+    }
+
+    // Counts are shown against the limits that decoding enforces, and the size against the most that the network
+    // accepts, so that anyone building a large transaction can see how close it is to them
+    let mut encoded_tx = vec![];
+    let _ = tx.encode(&mut encoded_tx);
+    writeln!(out, "    Signatures required  : {}/{}", signatures_count, MAXIMUM_ED25519_SIGNATURES_COUNT)?;
+    writeln!(out, "    Addresses            : {}/{}", tx.address_count(), MAXIMUM_ADDRESSES_COUNT)?;
+    writeln!(out, "    Instructions         : {}/{}", tx.instructions.len(), MAXIMUM_INSTRUCTIONS_COUNT)?;
+    writeln!(out, "    Size                 : {}/{} bytes", encoded_tx.len(), MAXIMUM_TRANSACTION_BYTES)?;
+    writeln!(out, "    Estimated base fee   : {} lamports", (signatures_count as u64) * LAMPORTS_PER_SIGNATURE)?;
+
+    // The prioritization fee is the compute unit price times the compute unit limit
+    let mut compute_unit_limit = None;
+    let mut compute_unit_price = None;
+
+    for instruction in &tx.instructions {
+        match instruction.decode_known() {
+            Some(KnownInstruction::SetComputeUnitLimit(units)) => compute_unit_limit = Some(units as u64),
+            Some(KnownInstruction::SetComputeUnitPrice(price)) => compute_unit_price = Some(price),
+            _ => ()
+        }
+    }
+
+    if let Some(compute_unit_price) = compute_unit_price {
+        let compute_unit_limit = compute_unit_limit.unwrap_or_else(|| {
+            let instructions_count = tx
+                .instructions
+                .iter()
+                .filter(|instruction| instruction.program_address != COMPUTE_BUDGET_PROGRAM_ADDRESS)
+                .count() as u64;
+            std::cmp::min(instructions_count * DEFAULT_INSTRUCTION_COMPUTE_UNIT_LIMIT, MAXIMUM_COMPUTE_UNIT_LIMIT)
+        });
+        let priority_fee =
+            ((compute_unit_limit as u128) * (compute_unit_price as u128)).div_ceil(MICRO_LAMPORTS_PER_LAMPORT);
+        writeln!(out, "    Prioritization fee   : {} lamports", priority_fee)?;
+    }
+
+    for (i, instruction) in tx.instructions.iter().enumerate() {
+        if program_filter.is_empty() || program_filter.contains(&instruction.program_address) {
+            print_instruction(out, i, instruction, sol)?;
+        }
+        else {
+            writeln!(
+                out,
+                "\n  Instruction {}: {} ({} accounts, {} bytes of data)",
+                i,
+                instruction.program_address,
+                instruction.addresses.len(),
+                instruction.data.len()
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+// Writes each signer of the transaction with the reasons that its signature is required: paying the fee, and being a
+// signer of instructions.  A signer that is neither is only required because the transaction lists it as a signer.
+fn print_signer_reasons(
+    out : &mut dyn Write,
+    tx : &Transaction
+) -> std::io::Result<()>
+{
+    writeln!(out, "\n  Signers:\n")?;
+
+    let fee_payer = tx.fee_payer().map(|fee_payer| &fee_payer.pubkey);
+
+    for signer in tx.signed_read_write_addresses.iter().chain(&tx.signed_read_only_addresses) {
+        let mut reasons = vec![];
+
+        if fee_payer == Some(&signer.pubkey) {
+            reasons.push("fee payer".to_string());
+        }
+
+        let instructions : Vec<String> = tx
+            .instructions
+            .iter()
+            .enumerate()
+            .filter(|(_, instruction)| {
+                instruction.addresses.iter().any(|(address, is_signed, _)| *is_signed && (address.0 == signer.pubkey.0))
+            })
+            .map(|(i, _)| i.to_string())
+            .collect();
+
+        match instructions.len() {
+            0 => (),
+            1 => reasons.push(format!("signer of instruction {}", instructions[0])),
+            _ => reasons.push(format!("signer of instructions {}", instructions.join(", ")))
+        }
+
+        if reasons.is_empty() {
+            reasons.push("listed as a signer, but signs no instruction".to_string());
+        }
+
+        writeln!(out, "    {:<44}  {}", signer.pubkey, reasons.join("; "))?;
+    }
+
+    Ok(())
+}
+
+fn print_instruction(
+    out : &mut dyn Write,
+    index : usize,
+    instruction : &Instruction,
+    sol : bool
+) -> std::io::Result<()>
+{
+    writeln!(out, "\n  Instruction {}:\n", index)?;
+    writeln!(out, "    Program   : {}", instruction.program_address)?;
+
+    // Each account is shown with the role it has in the transaction, which is what the program is given
+    for (i, (address, is_signed, is_read_write)) in instruction.addresses.iter().enumerate() {
+        let role = match (is_signed, is_read_write) {
+            (true, true) => "signer, writable",
+            (true, false) => "signer",
+            (false, true) => "writable",
+            (false, false) => "read-only"
+        };
+        writeln!(out, "    {}{} ({})", if i == 0 { "Accounts  : " } else { "            " }, address, role)?;
+    }
+
+    match instruction.decode_known() {
+        // Whole units are easier to check at a glance, but the exact amount is still shown alongside them
+        Some(KnownInstruction::Transfer(lamports, destination)) if sol => writeln!(
+            out,
+            "    Action    : Transfer {} SOL ({} lamports) to {}",
+            format_decimal(lamports, SOL_DECIMALS),
+            lamports,
+            destination
+        )?,
+
+        Some(KnownInstruction::TokenTransfer(amount, destination)) if sol && instruction.token_decimals().is_some() => {
+            writeln!(
+                out,
+                "    Action    : Transfer {} tokens ({} token units) to {}",
+                format_decimal(amount, instruction.token_decimals().unwrap()),
+                amount,
+                destination
+            )?
+        },
+
+        Some(known) => writeln!(out, "    Action    : {}", known)?,
+
+        // Instructions that can't be described are shown as a hex dump of their data
+        None => {
+            if instruction.data.is_empty() {
+                writeln!(out, "    Data      : None")?;
+            }
+            for (i, chunk) in instruction.data.chunks(32).enumerate() {
+                writeln!(out, "    {}{}", if i == 0 { "Data      : " } else { "            " }, hex(chunk))?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Formats amount, in units of 10^-decimals, as a decimal number without trailing zeros
+fn format_decimal(
+    amount : u64,
+    decimals : u8
+) -> String
+{
+    let digits = format!("{:0>width$}", amount, width = (decimals as usize) + 1);
+    let (whole, fraction) = digits.split_at(digits.len() - (decimals as usize));
+    let fraction = fraction.trim_end_matches('0');
+
+    if fraction.is_empty() {
+        whole.to_string()
+    }
+    else {
+        format!("{}.{}", whole, fraction)
+    }
+}
+
+fn hex(bytes : &[u8]) -> String
+{
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode_hex(s : &str) -> Option<Vec<u8>>
+{
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..s.len()).step_by(2).map(|i| s.get(i..(i + 2)).and_then(|b| u8::from_str_radix(b, 16).ok())).collect()
+}
+
+// Solana's compact-u16 encoding of lengths: 7 bits per byte, least significant first, with the high bit of each byte
+// but the last set; at most 3 bytes.  As in the Solana runtime, only the shortest encoding of a value is accepted, so
+// a final byte other than the first may not be zero, and the third byte only has 2 bits left to give.
+fn decode_compact_u16(r : &mut dyn std::io::Read) -> Result<u16, TxError>
+{
+    let mut buf = [0_u8; 3];
+
+    Transaction::read(r, &mut buf[0..1])?;
+
+    if (buf[0] & 0x80) == 0x80 {
+        Transaction::read(r, &mut buf[1..2])?;
+        if buf[1] & 0x80 == 0x80 {
+            Transaction::read(r, &mut buf[2..3])?;
+            if (buf[2] == 0) || (buf[2] > 0x03) {
+                return Err(TxError::InvalidCompactU16(buf));
+            }
+            Ok(((buf[0] as u16) & !0x80) | (((buf[1] as u16) & !0x80) << 7) | ((buf[2] as u16) << 14))
+        }
+        else if buf[1] == 0 {
+            Err(TxError::InvalidCompactU16(buf))
+        }
+        else {
+            Ok(((buf[0] as u16) & !0x80) | (((buf[1] as u16) & !0x80) << 7))
+        }
+    }
+    else {
+        Ok(buf[0] as u16)
+    }
+}
+
+fn encode_compact_u16(
+    mut u : u16,
+    w : &mut dyn std::io::Write
+) -> Result<(), TxError>
+{
+    let mut buf = [0_u8; 3];
+
+    let mut v = (u & 0x7F) as u8;
+    if u > 0x7F {
+        buf[0] = v | 0x80;
+        u >>= 7;
+        v = (u & 0x7F) as u8;
+        if u > 0x7F {
+            buf[1] = v | 0x80;
+            buf[2] = (u >> 7) as u8;
+            Transaction::write(w, &buf)
+        }
+        else {
+            buf[1] = v;
+            Transaction::write(w, &buf[0..2])
+        }
+    }
+    else {
+        buf[0] = v;
+        Transaction::write(w, &buf[0..1])
+    }
+}
+
+// Encodes an unsigned transaction that transfers lamports from the account of from, which also pays the fee, to the
+// account of to, using the System program
+fn build_transfer(
+    from : &Pubkey,
+    to : &Address,
+    lamports : u64,
+    blockhash : &Sha256Digest
+) -> Vec<u8>
+{
+    // A transfer to the payer's own account lists the account only once
+    let to_is_from = to.0 == from.0;
+
+    let tx = Transaction {
+        signed_read_write_addresses : vec![PubkeyWithSignature { pubkey : from.clone(), signature : None }],
+        signed_read_only_addresses : vec![],
+        unsigned_read_write_addresses : if to_is_from { vec![] } else { vec![to.clone()] },
+        unsigned_read_only_addresses : vec![SYSTEM_PROGRAM_ADDRESS],
+        recent_blockhash : Some(blockhash.clone()),
+        instructions : vec![Instruction {
+            program_address : SYSTEM_PROGRAM_ADDRESS,
+            addresses : vec![(Address(from.0), true, true), (to.clone(), to_is_from, true)],
+            data : [&SYSTEM_TRANSFER.to_le_bytes()[..], &lamports.to_le_bytes()].concat()
+        }]
+    };
+
+    let mut bytes = vec![];
+    let _ = tx.encode(&mut bytes);
+    bytes
+}
+
+// Prints Base64 wrapped at options.wrap_width columns (72 by default) and indented by options.indent spaces (4 by
+// default) for reading, or with no_wrap, as a single line, unindented unless an indent was given, that can be copied
+// whole into other tools
+fn print_base64(
+    bytes : &[u8],
+    options : &Options
+)
+{
+    let b = base64::encode(bytes);
+    let indent = " ".repeat(options.indent.unwrap_or(if options.no_wrap { 0 } else { 4 }));
+    if options.no_wrap {
+        println!("{}{}", indent, b);
+        return;
+    }
+    let width = options.wrap_width.unwrap_or(72);
+    for idx in (0..b.len()).step_by(width) {
+        let end = std::cmp::min(idx + width, b.len());
+        println!("{}{}", indent, &b[idx..end]);
+    }
+}
+
+impl CountingReader<'_>
+{
+    // Records that the named field starts at the current offset.  The name is only formatted when recording.
+    fn mark(
+        &mut self,
+        name : std::fmt::Arguments
+    )
+    {
+        if let Some(fields) = &mut self.fields {
+            fields.push((name.to_string(), self.count));
+        }
+    }
+}
+
+impl std::io::Read for CountingReader<'_>
+{
+    fn read(
+        &mut self,
+        buf : &mut [u8]
+    ) -> std::io::Result<usize>
+    {
+        let count = self.inner.read(buf)?;
+        self.count += count;
+        Ok(count)
+    }
+}
+
+impl Transaction
+{
+    // Decodes a transaction, giving the byte offset at which any error other than truncation was found.  Truncation
+    // is left as is, as it always occurs at the end of the input, and more input may yet complete the transaction.
+    pub fn decode(r : &mut dyn std::io::Read) -> Result<Self, TxError>
+    {
+        Self::decode_from(&mut CountingReader { inner : r, count : 0, fields : None })
+    }
+
+    // Decodes a transaction as decode does, also returning the name and starting offset of each field of the encoding
+    // that was reached, and the number of bytes read, for showing where each byte belongs even when decoding fails
+    pub fn decode_fields(bytes : &[u8]) -> (Result<Self, TxError>, Vec<(String, usize)>, usize)
+    {
+        let mut inner = bytes;
+
+        let mut r = CountingReader { inner : &mut inner, count : 0, fields : Some(vec![]) };
+
+        let result = Self::decode_from(&mut r);
+
+        (result, r.fields.unwrap_or_default(), r.count)
+    }
+
+    fn decode_from(r : &mut CountingReader) -> Result<Self, TxError>
+    {
+        Self::decode_counted(r).map_err(|error| match error {
+            TxError::Truncated => error,
+            error => TxError::AtOffset { offset : r.count, error : Box::new(error) }
+        })
+    }
+
+    fn decode_counted(r : &mut CountingReader) -> Result<Self, TxError>
+    {
+        r.mark(format_args!("Signatures count"));
+        let signatures_count = decode_compact_u16(r)?;
+
+        // Can't provide more signatures than allowed
+        if signatures_count > (MAXIMUM_ED25519_SIGNATURES_COUNT as u16) {
+            return Err(TxError::TooManySignatures {
+                maximum : MAXIMUM_ED25519_SIGNATURES_COUNT as usize,
+                actual : signatures_count as usize
+            });
+        }
+
+        // Signatures can't be found to be invalid until the addresses of their signers are read, so that the error
+        // can say which signer's signature is invalid
+        let mut signatures = Vec::<Option<Result<ed25519_dalek::Signature, ed25519_dalek::SignatureError>>>::new();
+
+        let mut buf = [0_u8; 64];
+
+        for i in 0..signatures_count {
+            r.mark(format_args!("Signature {}", i));
+            Self::read(r, &mut buf)?;
+            signatures.push(if buf == EMPTY_SIGNATURE_BYTES {
+                None
+            }
+            else {
+                Some(ed25519_dalek::Signature::from_bytes(&buf))
+            });
+        }
+
+        r.mark(format_args!("Message header"));
+        Self::read(r, &mut buf[0..3])?;
+
+        let total_signed_address_count = buf[0] as u16;
+
+        if total_signed_address_count > (MAXIMUM_ADDRESSES_COUNT as u16) {
+            return Err(TxError::TooManyAddresses {
+                maximum : MAXIMUM_ADDRESSES_COUNT as usize,
+                actual : total_signed_address_count as usize
+            });
+        }
+
+        // Our encoder always produces all signatures, but uses all zero signatures for those signatures which were
+        // not provided.  Other implementations may instead produce a short signatures list, which can only be
+        // signatures in order, with unsupplied signatures being zero.
+        if signatures_count > total_signed_address_count {
+            return Err(TxError::TooManySignatures {
+                maximum : total_signed_address_count as usize,
+                actual : signatures_count as usize
+            });
+        }
+
+        let signed_read_only_address_count = buf[1] as u16;
+
+        if signed_read_only_address_count > total_signed_address_count {
+            return Err(TxError::TooManyReadOnlySigners {
+                maximum : total_signed_address_count as usize,
+                actual : signed_read_only_address_count as usize
+            });
+        }
+
+        let signed_read_write_address_count = total_signed_address_count - signed_read_only_address_count;
+
+        if signed_read_write_address_count == 0 {
+            return Err(TxError::MissingFeePayer);
+        }
+
+        let unsigned_read_only_address_count = buf[2] as u16;
+
+        let minimum_address_count = total_signed_address_count + unsigned_read_only_address_count;
+
+        r.mark(format_args!("Addresses count"));
+        let actual_address_count = decode_compact_u16(r)?;
+
+        if actual_address_count > (MAXIMUM_ADDRESSES_COUNT as u16) {
+            return Err(TxError::TooManyAddresses {
+                maximum : MAXIMUM_ADDRESSES_COUNT as usize,
+                actual : actual_address_count as usize
+            });
+        }
+
+        if actual_address_count < minimum_address_count {
+            return Err(TxError::TooFewAddresses {
+                minimum : minimum_address_count as usize,
+                actual : actual_address_count as usize
+            });
+        }
+
+        let unsigned_read_write_address_count = actual_address_count - minimum_address_count;
+
+        let mut ret = Transaction {
+            signed_read_write_addresses : vec![],
+            signed_read_only_addresses : vec![],
+            unsigned_read_write_addresses : vec![],
+            unsigned_read_only_addresses : vec![],
+            recent_blockhash : None,
+            instructions : vec![]
+        };
+
+        let mut signatures_iter = signatures.into_iter();
+
+        // Addresses are marked by their index, which is how instructions refer to them
+        let mut address_index = 0;
+
+        // A signer appearing twice with the same permissions would leave which of its signature slots is meant
+        // ambiguous.  Appearing once with each set of permissions is left for the runtime to reject, as it is for any
+        // other address.
+        for _ in 0..signed_read_write_address_count {
+            r.mark(format_args!("Address {}", address_index));
+            address_index += 1;
+            let signer = Self::decode_signature_from_header(&mut signatures_iter, r)?;
+            if ret.signed_read_write_addresses.iter().any(|s| s.pubkey == signer.pubkey) {
+                return Err(TxError::DuplicateSigner(signer.pubkey));
+            }
+            ret.signed_read_write_addresses.push(signer);
+        }
+
+        for _ in 0..signed_read_only_address_count {
+            r.mark(format_args!("Address {}", address_index));
+            address_index += 1;
+            let signer = Self::decode_signature_from_header(&mut signatures_iter, r)?;
+            if ret.signed_read_only_addresses.iter().any(|s| s.pubkey == signer.pubkey) {
+                return Err(TxError::DuplicateSigner(signer.pubkey));
+            }
+            ret.signed_read_only_addresses.push(signer);
+        }
+
+        for _ in 0..unsigned_read_write_address_count {
+            r.mark(format_args!("Address {}", address_index));
+            address_index += 1;
+            ret.unsigned_read_write_addresses.push(Self::decode_address(r)?);
+        }
+
+        for _ in 0..unsigned_read_only_address_count {
+            r.mark(format_args!("Address {}", address_index));
+            address_index += 1;
+            ret.unsigned_read_only_addresses.push(Self::decode_address(r)?);
+        }
+
+        r.mark(format_args!("Recent blockhash"));
+        ret.recent_blockhash = Self::decode_recent_blockhash(r)?;
+
+        r.mark(format_args!("Instructions count"));
+        let instruction_count = decode_compact_u16(r)?;
+
+        if instruction_count > MAXIMUM_INSTRUCTIONS_COUNT {
+            return Err(TxError::TooManyInstructions {
+                maximum : MAXIMUM_INSTRUCTIONS_COUNT as usize,
+                actual : instruction_count as usize
+            });
+        }
+
+        for i in 0..instruction_count {
+            let i = i as usize;
+            r.mark(format_args!("Instruction {} program index", i));
+            Self::read(r, &mut buf[0..1])?;
+
+            let program_address = ret
+                .find_address_at_index(buf[0])
+                .ok_or(TxError::InvalidProgramIndex { instruction : i, index : buf[0] })?;
+
+            r.mark(format_args!("Instruction {} accounts", i));
+            let addresses_count = decode_compact_u16(r)?;
+
+            if addresses_count > MAXIMUM_INSTRUCTION_ADDRESS_INDEX_COUNT {
+                return Err(TxError::TooManyInstructionAddresses {
+                    instruction : i,
+                    maximum : MAXIMUM_INSTRUCTION_ADDRESS_INDEX_COUNT as usize,
+                    actual : addresses_count as usize
+                });
+            }
+
+            let mut addresses = Vec::<(Address, bool, bool)>::new();
+
+            for _ in 0..addresses_count {
+                Self::read(r, &mut buf[0..1])?;
+                addresses.push(
+                    ret.find_address_at_index(buf[0])
+                        .ok_or(TxError::InvalidAddressIndex { instruction : i, index : buf[0] })?
+                );
+            }
+
+            r.mark(format_args!("Instruction {} data", i));
+            let data_count = decode_compact_u16(r)?;
+
+            if data_count > MAXIMUM_INSTRUCTION_DATA_COUNT {
+                return Err(TxError::TooMuchInstructionData {
+                    instruction : i,
+                    maximum : MAXIMUM_INSTRUCTION_DATA_COUNT as usize,
+                    actual : data_count as usize
+                });
+            }
+
+            let mut data = vec![0_u8; data_count as usize];
+
+            Self::read(r, &mut data)?;
+
+            ret.instructions.push(Instruction { program_address : program_address.0, addresses, data });
+        }
+
+        Ok(ret)
+    }
+
+    // Return the message bytes of the transaction.
+    pub fn message(
+        &self,
+        w : &mut dyn std::io::Write
+    ) -> Result<(), TxError>
+    {
+        let signed_count = self.signed_read_write_addresses.len() + self.signed_read_only_addresses.len();
+
+        u8::try_from(signed_count)
+            .or(Err(TxError::TooManySignatures { maximum : u8::MAX as usize, actual : signed_count }))
+            .and_then(|u| Self::write(w, &[u]))?;
+
+        u8::try_from(self.signed_read_only_addresses.len())
+            .or(Err(TxError::TooManyReadOnlySigners {
+                maximum : u8::MAX as usize,
+                actual : self.signed_read_only_addresses.len()
+            }))
+            .and_then(|u| Self::write(w, &[u]))?;
+
+        Self::write(w, &[self.unsigned_read_only_addresses.len() as u8])?;
+
+        let recent_blockhash = self.recent_blockhash.as_ref().unwrap_or(&EMPTY_RECENT_BLOCKHASH);
+
+        if self.instructions.len() > (u16::MAX as usize) {
+            return Err(TxError::TooManyInstructions { maximum : u16::MAX as usize, actual : self.instructions.len() });
+        }
+
+        // compact-array of account addresses
+        encode_compact_u16(
+            (self.signed_read_write_addresses.len() +
+                self.signed_read_only_addresses.len() +
+                self.unsigned_read_write_addresses.len() +
+                self.unsigned_read_only_addresses.len()) as u16,
+            w
+        )?;
+
+        for a in self
+            .signed_read_write_addresses
+            .iter()
+            .chain(self.signed_read_only_addresses.iter())
+            .map(|s| &s.pubkey.0)
+            .chain(
+                self.unsigned_read_write_addresses.iter().chain(self.unsigned_read_only_addresses.iter()).map(|a| &a.0)
+            )
+        {
+            Self::write(w, a)?;
+        }
+
+        // recent blockhash
+        Self::write(w, &recent_blockhash.0)?;
+
+        // instructions
+        encode_compact_u16(self.instructions.len() as u16, w)?;
+
+        for (i, instruction) in self.instructions.iter().enumerate() {
+            // instruction program_id index
+            Self::write(
+                w,
+                std::slice::from_ref(
+                    &self
+                        .find_address_index(&instruction.program_address)
+                        .ok_or(TxError::AddressNotFound(instruction.program_address.clone()))?
+                )
+            )?;
+
+            // instruction address indices
+            encode_compact_u16(instruction.addresses.len() as u16, w)?;
+            for a in &instruction.addresses {
+                Self::write(
+                    w,
+                    std::slice::from_ref(&self.find_address_index(&a.0).ok_or(TxError::AddressNotFound(a.0.clone()))?)
+                )?;
+            }
+
+            // instruction data
+            let data_len = instruction.data.len();
+            if data_len > (MAXIMUM_INSTRUCTION_DATA_COUNT as usize) {
+                return Err(TxError::TooMuchInstructionData {
+                    instruction : i,
+                    maximum : MAXIMUM_INSTRUCTION_DATA_COUNT as usize,
+                    actual : data_len
+                });
+            }
+            encode_compact_u16(data_len as u16, w)?;
+            Self::write(w, instruction.data.as_slice())?;
+        }
+        Ok(())
+    }
+
+    // The fee payer is always the first signed read-write address.  Returns None if there are no signed read-write
+    // addresses, which decode never produces but which a hand-built Transaction may have.
+    pub fn fee_payer(&self) -> Option<&PubkeyWithSignature>
+    {
+        self.signed_read_write_addresses.first()
+    }
+
+    // Every signed address must provide a signature, whether read-write or read-only
+    pub fn required_signatures_count(&self) -> usize
+    {
+        self.signed_read_write_addresses.len() + self.signed_read_only_addresses.len()
+    }
+
+    // The number of signature slots that have been filled in
+    pub fn present_signatures_count(&self) -> usize
+    {
+        self.signed_read_write_addresses
+            .iter()
+            .chain(self.signed_read_only_addresses.iter())
+            .filter(|s| s.signature.is_some())
+            .count()
+    }
+
+    // A durable nonce transaction is one whose first instruction advances a nonce account.  Such transactions carry
+    // the nonce value in place of a recent blockhash.
+    pub fn is_durable_nonce(&self) -> bool
+    {
+        self.instructions.first().is_some_and(|instruction| {
+            instruction.program_address == SYSTEM_PROGRAM_ADDRESS &&
+                instruction.data.get(0..4) == Some(&SYSTEM_ADVANCE_NONCE_ACCOUNT.to_le_bytes())
+        })
+    }
+
+    // Iterates over addresses that still need to provide a signature.  The same pubkey may appear in more than one
+    // signature slot (i.e. both as a read-write and a read-only signer); it is only returned once.  dedup() only
+    // removes adjacent duplicates, so the sort must come before it.
+    pub fn needed_signatures(&self) -> impl Iterator<Item = Pubkey>
+    {
+        let mut v : Vec<Pubkey> = self
+            .signed_read_write_addresses
+            .iter()
+            .filter_map(|a| {
+                if a.signature.is_some() {
+                    None
+                }
+                else {
+                    Some(a.pubkey.clone())
+                }
+            })
+            .chain(self.signed_read_only_addresses.iter().filter_map(|a| {
+                if a.signature.is_some() {
+                    None
+                }
+                else {
+                    Some(a.pubkey.clone())
+                }
+            }))
+            .collect();
+
+        v.sort_by_key(|a| format!("{}", a));
+
+        v.dedup();
+
+        v.into_iter()
+    }
+
+    // Iterates over addresses that still need to provide a signature in the order of their signature slots: the fee
+    // payer first, then the other read-write signers, then the read-only signers.  A pubkey in more than one slot is
+    // only returned for the first of them.  This is the order in which to show them to the user.
+    pub fn needed_signatures_ordered(&self) -> impl Iterator<Item = Pubkey> + '_
+    {
+        let mut seen = HashSet::<Pubkey>::new();
+
+        self.signed_read_write_addresses
+            .iter()
+            .chain(&self.signed_read_only_addresses)
+            .filter(|a| a.signature.is_none())
+            .map(|a| a.pubkey.clone())
+            .filter(move |pubkey| seen.insert(pubkey.clone()))
+    }
+
+    // A signer provides a single signature over the message, so the signature is applied to every slot that the pubkey
+    // occupies in either of the signed address lists
+    pub fn sign(
+        &mut self,
+        pubkey : &Pubkey,
+        signature : ed25519_dalek::Signature
+    ) -> Result<(), TxError>
+    {
+        for i in 0..self.signed_read_write_addresses.len() {
+            if self.signed_read_write_addresses[i].pubkey == *pubkey {
+                self.signed_read_write_addresses[i].signature = Some(signature);
+            }
+        }
+
+        for i in 0..self.signed_read_only_addresses.len() {
+            if self.signed_read_only_addresses[i].pubkey == *pubkey {
+                self.signed_read_only_addresses[i].signature = Some(signature);
+            }
+        }
+
+        Ok(())
+    }
+
+    // Returns the index of an instruction account, which is listed with the same role as well as the same address.  An
+    // address may be listed more than once with different roles, which find_address_index can't tell apart.
+    fn find_account_index(
+        &self,
+        (address, is_signer, is_writable) : &(Address, bool, bool)
+    ) -> Option<u8>
+    {
+        addresses_with_roles(self)
+            .position(|account| (&account.0 == address) && (account.1 == *is_signer) && (account.2 == *is_writable))
+            .map(|index| index as u8)
+    }
+
+    // Returns the account that an instruction's account index refers to, or None if the index is out of range.
+    // Accounts are indexed in the order that they're encoded: signed read-write, signed read-only, unsigned
+    // read-write, and then unsigned read-only.
+    pub fn account_at(
+        &self,
+        index : u8
+    ) -> Option<AccountMeta>
+    {
+        let account = |address : &[u8; 32], is_signer, is_writable| {
+            Some(AccountMeta { address : Address(*address), is_signer, is_writable })
+        };
+
+        let mut uindex = index as usize;
+
+        if uindex < self.signed_read_write_addresses.len() {
+            return account(&self.signed_read_write_addresses[uindex].pubkey.0, true, true);
+        }
+
+        uindex -= self.signed_read_write_addresses.len();
+
+        if uindex < self.signed_read_only_addresses.len() {
+            return account(&self.signed_read_only_addresses[uindex].pubkey.0, true, false);
+        }
+
+        uindex -= self.signed_read_only_addresses.len();
+
+        if uindex < self.unsigned_read_write_addresses.len() {
+            return account(&self.unsigned_read_write_addresses[uindex].0, false, true);
+        }
+
+        uindex -= self.unsigned_read_write_addresses.len();
+
+        if uindex < self.unsigned_read_only_addresses.len() {
+            return account(&self.unsigned_read_only_addresses[uindex].0, false, false);
+        }
+
+        None
+    }
+
+    // Removes all signatures, for a transaction that is to be signed anew
+    pub fn strip_signatures(&mut self)
+    {
+        for slot in self.signed_read_write_addresses.iter_mut().chain(self.signed_read_only_addresses.iter_mut()) {
+            slot.signature = None;
+        }
+    }
+
+    // Applies many signatures in one pass over the signature slots, where calling sign() for each would make one pass
+    // per signature.  Each slot's signature is found by a linear search; for the number of signatures that a
+    // transaction can have, this is faster than building a map from pubkey to slots first, as the sign bench shows.
+    pub fn sign_all(
+        &mut self,
+        signatures : &[(Pubkey, ed25519_dalek::Signature)]
+    )
+    {
+        for slot in self.signed_read_write_addresses.iter_mut().chain(self.signed_read_only_addresses.iter_mut()) {
+            if let Some((_, signature)) = signatures.iter().find(|(pubkey, _)| *pubkey == slot.pubkey) {
+                slot.signature = Some(*signature);
+            }
+        }
+    }
+
+    pub fn encode(
+        &self,
+        w : &mut dyn std::io::Write
+    ) -> Result<(), TxError>
+    {
+        let total_signatures = self.required_signatures_count();
+
+        if total_signatures > (u16::MAX as usize) {
+            return Err(TxError::TooManySignatures { maximum : u16::MAX as usize, actual : total_signatures });
+        }
+
+        encode_compact_u16(total_signatures as u16, w)?;
+
+        for signature in self.signed_read_write_addresses.iter().chain(&self.signed_read_only_addresses) {
+            Self::encode_signature(signature.signature, w)?;
+        }
+
+        self.message(w)
+    }
+
+    // Returns the fully decoded transaction as JSON.  Addresses, signatures and the recent blockhash are Base58
+    // encoded, instruction accounts are given as indices into the transaction's addresses, and instruction data is
+    // Base64 encoded.
+    pub fn to_json(&self) -> serde_json::Value
+    {
+        let signed = |addresses : &Vec<PubkeyWithSignature>| -> Vec<serde_json::Value> {
+            addresses
+                .iter()
+                .map(|a| {
+                    serde_json::json!({
+                        "pubkey" : a.pubkey.to_string(),
+                        "signature" : a.signature.map(|s| bs58::encode(s.to_bytes()).into_string())
+                    })
+                })
+                .collect()
+        };
+
+        let unsigned = |addresses : &Vec<Address>| -> Vec<String> { addresses.iter().map(|a| a.to_string()).collect() };
+
+        let instructions : Vec<serde_json::Value> = self
+            .instructions
+            .iter()
+            .map(|instruction| {
+                serde_json::json!({
+                    "program_id" : instruction.program_address.to_string(),
+                    "accounts" : instruction
+                        .addresses
+                        .iter()
+                        .map(|account| self.find_account_index(account))
+                        .collect::<Vec<Option<u8>>>(),
+                    "data" : base64::encode(&instruction.data)
+                })
+            })
+            .collect();
+
+        serde_json::json!({
+            "signed_read_write_addresses" : signed(&self.signed_read_write_addresses),
+            "signed_read_only_addresses" : signed(&self.signed_read_only_addresses),
+            "unsigned_read_write_addresses" : unsigned(&self.unsigned_read_write_addresses),
+            "unsigned_read_only_addresses" : unsigned(&self.unsigned_read_only_addresses),
+            "recent_blockhash" : self.recent_blockhash.as_ref().map(|b| bs58::encode(b.0).into_string()),
+            "instructions" : instructions
+        })
+    }
+
+    // The JSON form of a transaction used by the solana command line tools and web3.js, with all signatures, including
+    // those not yet made, listed in the order of their signers' addresses, and instruction data Base58 encoded
+    pub fn to_cli_json(&self) -> serde_json::Value
+    {
+        let signatures : Vec<String> = self
+            .signed_read_write_addresses
+            .iter()
+            .chain(self.signed_read_only_addresses.iter())
+            .map(|a| bs58::encode(a.signature.map(|s| s.to_bytes()).unwrap_or(EMPTY_SIGNATURE_BYTES)).into_string())
+            .collect();
+
+        let account_keys : Vec<String> = self
+            .signed_read_write_addresses
+            .iter()
+            .chain(self.signed_read_only_addresses.iter())
+            .map(|a| a.pubkey.to_string())
+            .chain(
+                self.unsigned_read_write_addresses
+                    .iter()
+                    .chain(self.unsigned_read_only_addresses.iter())
+                    .map(|a| a.to_string())
+            )
+            .collect();
+
+        let instructions : Vec<serde_json::Value> = self
+            .instructions
+            .iter()
+            .map(|instruction| {
+                serde_json::json!({
+                    "programIdIndex" : self.find_address_index(&instruction.program_address),
+                    "accounts" : instruction
+                        .addresses
+                        .iter()
+                        .map(|account| self.find_account_index(account))
+                        .collect::<Vec<Option<u8>>>(),
+                    "data" : bs58::encode(&instruction.data).into_string()
+                })
+            })
+            .collect();
+
+        let recent_blockhash = self.recent_blockhash.as_ref().map(|b| b.0).unwrap_or([0_u8; 32]);
+
+        serde_json::json!({
+            "signatures" : signatures,
+            "message" : {
+                "header" : {
+                    "numRequiredSignatures" : self.required_signatures_count(),
+                    "numReadonlySignedAccounts" : self.signed_read_only_addresses.len(),
+                    "numReadonlyUnsignedAccounts" : self.unsigned_read_only_addresses.len()
+                },
+                "accountKeys" : account_keys,
+                "recentBlockhash" : bs58::encode(recent_blockhash).into_string(),
+                "instructions" : instructions
+            }
+        })
+    }
+
+    // Builds a transaction from JSON in the form produced by to_json.  Instruction account indices and program ids are
+    // checked against the transaction's addresses, which also supply each account's signer and writable flags.
+    pub fn from_json(value : &serde_json::Value) -> Result<Self, String>
+    {
+        let signed = |field : &str| -> Result<Vec<PubkeyWithSignature>, String> {
+            Self::json_array(value, field)?
+                .iter()
+                .map(|a| {
+                    let pubkey = Pubkey(Self::json_base58::<32>(&a["pubkey"], &format!("{} pubkey", field))?);
+                    let signature = match &a["signature"] {
+                        serde_json::Value::Null => None,
+                        signature => Some(
+                            ed25519_dalek::Signature::from_bytes(&Self::json_base58::<64>(
+                                signature,
+                                &format!("{} signature", field)
+                            )?)
+                            .map_err(|e| format!("Invalid {} signature: {}", field, e))?
+                        )
+                    };
+                    Ok(PubkeyWithSignature { pubkey, signature })
+                })
+                .collect()
+        };
+
+        let unsigned = |field : &str| -> Result<Vec<Address>, String> {
+            Self::json_array(value, field)?.iter().map(|a| Ok(Address(Self::json_base58::<32>(a, field)?))).collect()
+        };
+
+        let mut ret = Transaction {
+            signed_read_write_addresses : signed("signed_read_write_addresses")?,
+            signed_read_only_addresses : signed("signed_read_only_addresses")?,
+            unsigned_read_write_addresses : unsigned("unsigned_read_write_addresses")?,
+            unsigned_read_only_addresses : unsigned("unsigned_read_only_addresses")?,
+            recent_blockhash : match &value["recent_blockhash"] {
+                serde_json::Value::Null => None,
+                recent_blockhash => Some(Sha256Digest(Self::json_base58::<32>(recent_blockhash, "recent_blockhash")?))
+            },
+            instructions : vec![]
+        };
+
+        if ret.signed_read_write_addresses.is_empty() {
+            return Err("Minimum signed address count of 1 required for fee payer".to_string());
+        }
+
+        let signed_count = ret.signed_read_write_addresses.len() + ret.signed_read_only_addresses.len();
+
+        if signed_count > (MAXIMUM_ED25519_SIGNATURES_COUNT as usize) {
+            return Err(format!(
+                "Too many signed addresses: expected at most {}, got {}",
+                MAXIMUM_ED25519_SIGNATURES_COUNT, signed_count
+            ));
+        }
+
+        let address_count = ret.address_count();
+
+        if address_count > (MAXIMUM_ADDRESSES_COUNT as usize) {
+            return Err(format!(
+                "Too many addresses: expected at most {}, got {}",
+                MAXIMUM_ADDRESSES_COUNT, address_count
+            ));
+        }
+
+        let instructions = Self::json_array(value, "instructions")?;
+
+        if instructions.len() > (MAXIMUM_INSTRUCTIONS_COUNT as usize) {
+            return Err(format!(
+                "Too many instructions: expected at most {}, got {}",
+                MAXIMUM_INSTRUCTIONS_COUNT,
+                instructions.len()
+            ));
+        }
+
+        for (i, instruction) in instructions.iter().enumerate() {
+            let program_address = Address(Self::json_base58::<32>(
+                &instruction["program_id"],
+                &format!("program_id of instruction {}", i)
+            )?);
+
+            if ret.find_address_index(&program_address).is_none() {
+                return Err(format!("Program id of instruction {} is not one of the transaction's addresses", i));
+            }
+
+            let addresses = Self::json_array(instruction, "accounts")
+                .map_err(|e| format!("{} of instruction {}", e, i))?
+                .iter()
+                .map(|index| {
+                    index
+                        .as_u64()
+                        .and_then(|index| u8::try_from(index).ok())
+                        .and_then(|index| ret.find_address_at_index(index))
+                        .ok_or(format!("Invalid address index {} referenced from instruction {}", index, i))
+                })
+                .collect::<Result<Vec<(Address, bool, bool)>, String>>()?;
+
+            let data =
+                instruction["data"].as_str().ok_or(format!("Missing data of instruction {}", i)).and_then(|data| {
+                    base64::decode(data).map_err(|e| format!("Invalid Base64 data of instruction {}: {}", i, e))
+                })?;
+
+            ret.instructions.push(Instruction { program_address, addresses, data });
+        }
+
+        Ok(ret)
+    }
+
+    fn address_count(&self) -> usize
+    {
+        self.signed_read_write_addresses.len() +
+            self.signed_read_only_addresses.len() +
+            self.unsigned_read_write_addresses.len() +
+            self.unsigned_read_only_addresses.len()
+    }
+
+    fn json_array<'a>(
+        value : &'a serde_json::Value,
+        field : &str
+    ) -> Result<&'a Vec<serde_json::Value>, String>
+    {
+        value[field].as_array().ok_or(format!("Missing {} array", field))
+    }
+
+    // Decodes a Base58 encoded JSON string which must decode to exactly N bytes
+    fn json_base58<const N: usize>(
+        value : &serde_json::Value,
+        what : &str
+    ) -> Result<[u8; N], String>
+    {
+        let s = value.as_str().ok_or(format!("Missing {}", what))?;
+
+        bs58::decode(s)
+            .into_vec()
+            .ok()
+            .and_then(|v| <[u8; N]>::try_from(v).ok())
+            .ok_or(format!("Invalid {}: {}", what, s))
+    }
+
+    fn decode_signature_from_header(
+        signatures : impl IntoIterator<Item = Option<Result<ed25519_dalek::Signature, ed25519_dalek::SignatureError>>>,
+        r : &mut dyn std::io::Read
+    ) -> Result<PubkeyWithSignature, TxError>
+    {
+        let pubkey = Pubkey(Self::decode_address(r)?.0);
+
+        let signature = match signatures.into_iter().next().flatten() {
+            Some(Ok(signature)) => Some(signature),
+            Some(Err(error)) => return Err(TxError::InvalidSignature { signer : pubkey, error }),
+            None => None
+        };
+
+        Ok(PubkeyWithSignature { pubkey, signature })
+    }
+
+    fn decode_address(r : &mut dyn std::io::Read) -> Result<Address, TxError>
+    {
+        let mut buf = [0_u8; 32];
+        Self::read(r, &mut buf)?;
+        Ok(Address(buf))
+    }
+
+    fn decode_recent_blockhash(r : &mut dyn std::io::Read) -> Result<Option<Sha256Digest>, TxError>
+    {
+        let mut buf = [0_u8; 32];
+
+        Self::read(r, &mut buf)?;
+
+        if buf == EMPTY_RECENT_BLOCKHASH.0 {
+            Ok(None)
+        }
+        else {
+            Ok(Some(Sha256Digest(buf)))
+        }
+    }
+
+    // Searching is done irrespective of account permissions.  This matches the expected Solana runtime behavior,
+    // where the execution system will perform a similar action.  It is technically possible to encode the same
+    // address with multiple permissions versions, but the runtime will reject such a transaction with an error about
+    // "Account loaded twice"
+    fn find_address_index(
+        &self,
+        address : &Address
+    ) -> Option<u8>
+    {
+        if let Some(index) = self.signed_read_write_addresses.iter().position(|s| address == &s.pubkey) {
+            return Some(index as u8);
+        }
+
+        let mut offset = self.signed_read_write_addresses.len();
+
+        if let Some(index) = self.signed_read_only_addresses.iter().position(|s| address == &s.pubkey) {
+            return Some((index + offset) as u8);
+        }
+
+        offset += self.signed_read_only_addresses.len();
+
+        if let Some(index) = self.unsigned_read_write_addresses.iter().position(|a| address == a) {
+            return Some((index + offset) as u8);
+        }
+
+        offset += self.unsigned_read_write_addresses.len();
+
+        self.unsigned_read_only_addresses.iter().position(|a| address == a).map(|index| (index + offset) as u8)
+    }
+
+    // Returns (address, is_signed, read_write)
+    fn find_address_at_index(
+        &self,
+        index : u8
+    ) -> Option<(Address, bool, bool)>
+    {
+        self.account_at(index).map(|account| (account.address, account.is_signer, account.is_writable))
+    }
+
+    fn encode_signature(
+        signature : Option<ed25519_dalek::Signature>,
+        w : &mut dyn std::io::Write
+    ) -> Result<(), TxError>
+    {
+        Self::write(w, signature.map(|s| s.to_bytes()).unwrap_or(EMPTY_SIGNATURE_BYTES).as_slice())
+    }
+
+    fn read(
+        r : &mut dyn std::io::Read,
+        buf : &mut [u8]
+    ) -> Result<(), TxError>
+    {
+        match r.read_exact(buf) {
+            Ok(_) => Ok(()),
+
+            Err(e) => match e.kind() {
+                std::io::ErrorKind::UnexpectedEof => Err(TxError::Truncated),
+
+                _ => Err(TxError::Io(e))
+            }
+        }
+    }
+
+    fn write(
+        w : &mut dyn std::io::Write,
+        buf : &[u8]
+    ) -> Result<(), TxError>
+    {
+        w.write_all(buf).map_err(TxError::Io)
+    }
+}
+
+impl Options
+{
+    // Anything that is not a recognized option is taken to be the path to a key file
+    pub fn from_args(args : impl Iterator<Item = String>) -> Self
+    {
+        let mut options = Options::default();
+
+        let mut args = args;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--help" => {
+                    println!("{}", usage_string());
+                    std::process::exit(0);
+                },
+
+                "--no-prompt" => options.no_prompt = true,
+
+                "--sign-message" => options.sign_message = true,
+
+                "--addresses" => options.addresses = true,
+
+                "--addresses-csv" => options.addresses_csv = true,
+
+                "--inspect" => options.inspect = true,
+
+                "--dump-raw" => options.dump_raw = true,
+
+                "--list-instructions" => options.list_instructions = true,
+
+                "--self-test" => options.self_test = true,
+
+                "--allow-empty-blockhash" => options.allow_empty_blockhash = true,
+
+                "--no-challenge" => options.no_challenge = true,
+
+                "--assume-yes" | "-y" => options.assume_yes = true,
+
+                "--no-key-prompt" => options.no_key_prompt = true,
+
+                "--count" => options.count = true,
+
+                "--sol" => options.sol = true,
+
+                "--explain" => options.explain = true,
+
+                "--require-complete" => options.require_complete = true,
+
+                "--decode-json" => options.decode_json = true,
+
+                "--encode-json" => options.encode_json = true,
+
+                "--emit-message" => options.emit_message = true,
+
+                "--merge" => options.merge = true,
+
+                "--binary" => options.binary = true,
+
+                "--length-prefixed" => {
+                    options.binary = true;
+                    options.length_prefixed = true;
+                },
+
+                "--concatenated" => options.concatenated = true,
+
+                "--keys-from-stdin" => options.keys_from_stdin = true,
+
+                "--mnemonic-file" => options.mnemonic_file = Some(required_arg("--mnemonic-file", args.next())),
+
+                "--keyring" => options.keyring = Some(required_arg("--keyring", args.next())),
+
+                "--passphrase-file" => options.passphrase_file = Some(required_arg("--passphrase-file", args.next())),
+
+                "--no-wrap" => options.no_wrap = true,
+
+                "--wrap-width" => {
+                    let width = parse_amount_arg("--wrap-width", args.next());
+                    if width == 0 {
+                        eprintln!("\n  {}\n", color_err("Invalid --wrap-width 0", COLOR_RED));
+                        std::process::exit(1);
+                    }
+                    options.wrap_width = Some(width as usize);
+                },
+
+                "--indent" => options.indent = Some(parse_amount_arg("--indent", args.next()) as usize),
+
+                "--cli-json" => options.cli_json = true,
+
+                "--signature-format" => {
+                    let value = args.next().unwrap_or_default();
+                    options.signature_format = match value.as_str() {
+                        "base58" => SignatureFormat::Base58,
+                        "base64" => SignatureFormat::Base64,
+                        "hex" => SignatureFormat::Hex,
+                        _ => {
+                            eprintln!(
+                                "\n  {}\n",
+                                color_err(
+                                    &format!("Invalid --signature-format {}: expected base58, base64 or hex", value),
+                                    COLOR_RED
+                                )
+                            );
+                            std::process::exit(1);
+                        }
+                    };
+                },
+
+                "--signature-only" => options.signature_only = true,
+
+                "--strip-signatures" => options.strip_signatures = true,
+
+                "--show-pubkeys" => options.show_pubkeys = true,
+
+                "--policy-command" => options.policy_command = Some(required_arg("--policy-command", args.next())),
+
+                "--insecure-echo" => INSECURE_ECHO.store(true, std::sync::atomic::Ordering::Relaxed),
+
+                "--password-fd" => {
+                    let value = args.next().unwrap_or_default();
+                    options.password_fd = Some(value.parse().unwrap_or_else(|_| {
+                        eprintln!("\n  {}\n", color_err(&format!("Invalid --password-fd {}", value), COLOR_RED));
+                        std::process::exit(1);
+                    }));
+                },
+
+                "--max-lamports" => options.max_lamports = Some(parse_amount_arg("--max-lamports", args.next())),
+
+                "--max-signers" => options.max_signers = Some(parse_amount_arg("--max-signers", args.next())),
+
+                "--max-token-amount" => {
+                    options.max_token_amount = Some(parse_amount_arg("--max-token-amount", args.next()))
+                },
+
+                "--allow-destination" => {
+                    let value = args.next().unwrap_or_default();
+                    options.allowed_destinations.push(value.parse().unwrap_or_else(|e| {
+                        eprintln!(
+                            "\n  {}\n",
+                            color_err(&format!("Invalid --allow-destination {}: {}", value, e), COLOR_RED)
+                        );
+                        std::process::exit(1);
+                    }));
+                },
+
+                "--signer" => {
+                    let value = args.next().unwrap_or_default();
+                    options.signers.push(value.parse().unwrap_or_else(|e| {
+                        eprintln!("\n  {}\n", color_err(&format!("Invalid --signer {}: {}", value, e), COLOR_RED));
+                        std::process::exit(1);
+                    }));
+                },
+
+                "--program-filter" => {
+                    let value = args.next().unwrap_or_default();
+                    options.program_filter.push(value.parse().unwrap_or_else(|e| {
+                        eprintln!(
+                            "\n  {}\n",
+                            color_err(&format!("Invalid --program-filter {}: {}", value, e), COLOR_RED)
+                        );
+                        std::process::exit(1);
+                    }));
+                },
+
+                "--ata" => {
+                    let value = args.next().unwrap_or_default();
+                    options.ata_mint = Some(value.parse().unwrap_or_else(|e| {
+                        eprintln!("\n  {}\n", color_err(&format!("Invalid --ata {}: {}", value, e), COLOR_RED));
+                        std::process::exit(1);
+                    }));
+                },
+
+                "--generate" => options.generate = Some(required_arg("--generate", args.next())),
+
+                "--pubkey-prefix" => {
+                    let value = args.next().unwrap_or_default();
+                    if !is_base58(&value) {
+                        eprintln!(
+                            "\n  {}\n",
+                            color_err(
+                                &format!("Invalid --pubkey-prefix {}: expected Base58 characters", value),
+                                COLOR_RED
+                            )
+                        );
+                        std::process::exit(1);
+                    }
+                    options.pubkey_prefix = Some(value);
+                },
+
+                "--build-transfer" => {
+                    let (from, to) = (args.next().unwrap_or_default(), args.next().unwrap_or_default());
+                    let invalid = |value : &str, e : String| -> ! {
+                        eprintln!(
+                            "\n  {}\n",
+                            color_err(&format!("Invalid --build-transfer {}: {}", value, e), COLOR_RED)
+                        );
+                        std::process::exit(1);
+                    };
+                    options.build_transfer = Some((
+                        from.parse().unwrap_or_else(|e| invalid(&from, e)),
+                        to.parse().unwrap_or_else(|e| invalid(&to, e)),
+                        parse_amount_arg("--build-transfer", args.next())
+                    ));
+                },
+
+                "--blockhash" => {
+                    let value = args.next().unwrap_or_default();
+                    options.blockhash = Some(Sha256Digest(decode_base58_pubkey(&value).unwrap_or_else(|e| {
+                        eprintln!("\n  {}\n", color_err(&format!("Invalid --blockhash {}: {}", value, e), COLOR_RED));
+                        std::process::exit(1);
+                    })));
+                },
+
+                "--seed-hex" => {
+                    let value = args.next().unwrap_or_default();
+                    options.seeds.push(decode_hex(&value).filter(|seed| seed.len() == 64).unwrap_or_else(|| {
+                        eprintln!("\n  {}\n", color_err("Invalid --seed-hex: expected 128 hex digits", COLOR_RED));
+                        std::process::exit(1);
+                    }));
+                },
+
+                // Not listed in the usage, as it is only for test fixtures, which need the same keys and the same
+                // behavior on every run in order to check the exact bytes of signatures.  The seed is taken as by
+                // --seed-hex, but may be as short as a secret key.  Never to be used for keys that hold real funds,
+                // as the seed is on the command line.
+                "--test-seed" => {
+                    let value = args.next().unwrap_or_default();
+                    let seed =
+                        decode_hex(&value).filter(|seed| (ed25519_dalek::SECRET_KEY_LENGTH..=64).contains(&seed.len()));
+                    options.seeds.push(seed.unwrap_or_else(|| {
+                        eprintln!(
+                            "\n  {}\n",
+                            color_err("Invalid --test-seed: expected 64 to 128 hex digits", COLOR_RED)
+                        );
+                        std::process::exit(1);
+                    }));
+                    TEST_MODE.store(true, std::sync::atomic::Ordering::Relaxed);
+                },
+
+                "--derivation-path" => {
+                    let value = args.next().unwrap_or_default();
+                    options.derivation_path = Some(value.parse().unwrap_or_else(|e| {
+                        eprintln!(
+                            "\n  {}\n",
+                            color_err(&format!("Invalid --derivation-path {}: {}", value, e), COLOR_RED)
+                        );
+                        std::process::exit(1);
+                    }));
+                },
+
+                "--signature" => {
+                    let value = args.next().unwrap_or_default();
+                    options.signatures.push(parse_signature_arg(&value).unwrap_or_else(|e| {
+                        eprintln!("\n  {}\n", color_err(&format!("Invalid --signature {}: {}", value, e), COLOR_RED));
+                        std::process::exit(1);
+                    }));
+                },
+
+                _ => options.key_files.push(arg)
+            }
+        }
+
+        options
+    }
+}
+
+impl LookupTable
+{
+    // Decodes the data of an address lookup table account, as it is held by the address lookup table program: a u32
+    // state (1 for an initialized table), the u64 deactivation slot, the u64 slot and u8 starting index of the last
+    // extension, an optional authority, and then padding up to the addresses
+    pub fn decode(data : &[u8]) -> Result<Self, String>
+    {
+        if data.len() < LOOKUP_TABLE_HEADER_BYTES {
+            return Err(format!("Expected at least {} bytes, got {}", LOOKUP_TABLE_HEADER_BYTES, data.len()));
+        }
+
+        match u32::from_le_bytes(data[0..4].try_into().unwrap()) {
+            1 => (),
+            0 => return Err("Lookup table is uninitialized".to_string()),
+            state => return Err(format!("Unknown lookup table state {}", state))
+        }
+
+        let addresses_bytes = &data[LOOKUP_TABLE_HEADER_BYTES..];
+
+        if !addresses_bytes.len().is_multiple_of(32) {
+            return Err(format!("Addresses take {} bytes, which is not a multiple of 32", addresses_bytes.len()));
+        }
+
+        if (addresses_bytes.len() / 32) > MAXIMUM_LOOKUP_TABLE_ADDRESSES_COUNT {
+            return Err(format!(
+                "Too many addresses: expected at most {}, got {}",
+                MAXIMUM_LOOKUP_TABLE_ADDRESSES_COUNT,
+                addresses_bytes.len() / 32
+            ));
+        }
+
+        let authority = match data[21] {
+            0 => None,
+            1 => Some(Pubkey(data[22..54].try_into().unwrap())),
+            tag => return Err(format!("Invalid authority tag {}", tag))
+        };
+
+        Ok(LookupTable {
+            deactivation_slot : u64::from_le_bytes(data[4..12].try_into().unwrap()),
+            last_extended_slot : u64::from_le_bytes(data[12..20].try_into().unwrap()),
+            authority,
+            addresses : addresses_bytes.chunks(32).map(|chunk| Address(chunk.try_into().unwrap())).collect()
+        })
+    }
+}
+
+impl Instruction
+{
+    // Decodes the instruction if it is one that solsign knows how to describe
+    pub fn decode_known(&self) -> Option<KnownInstruction>
+    {
+        if self.program_address == COMPUTE_BUDGET_PROGRAM_ADDRESS {
+            match self.data.split_first() {
+                Some((&COMPUTE_BUDGET_SET_COMPUTE_UNIT_LIMIT, units)) => {
+                    Some(KnownInstruction::SetComputeUnitLimit(u32::from_le_bytes(units.try_into().ok()?)))
+                },
+
+                Some((&COMPUTE_BUDGET_SET_COMPUTE_UNIT_PRICE, price)) => {
+                    Some(KnownInstruction::SetComputeUnitPrice(u64::from_le_bytes(price.try_into().ok()?)))
+                },
+
+                _ => None
+            }
+        }
+        else if self.program_address == SYSTEM_PROGRAM_ADDRESS {
+            let rest = self.data.get(4..)?;
+            let u64_at = |offset : usize| Some(u64::from_le_bytes(rest.get(offset..)?.get(..8)?.try_into().ok()?));
+            let address = |index : usize| Some(self.addresses.get(index)?.0.clone());
+            // The destination of a transfer with seed follows the funding account and the base account
+            match u32::from_le_bytes(self.data.get(0..4)?.try_into().ok()?) {
+                SYSTEM_CREATE_ACCOUNT => Some(KnownInstruction::CreateAccount(u64_at(0)?, address(1)?)),
+
+                SYSTEM_TRANSFER => Some(KnownInstruction::Transfer(u64_at(0)?, address(1)?)),
+
+                // The lamports follow the base address and the seed, which is prefixed by its length
+                SYSTEM_CREATE_ACCOUNT_WITH_SEED => {
+                    let seed_length = usize::try_from(u64_at(32)?).ok()?;
+                    Some(KnownInstruction::CreateAccount(u64_at(40_usize.checked_add(seed_length)?)?, address(1)?))
+                },
+
+                SYSTEM_ADVANCE_NONCE_ACCOUNT => Some(KnownInstruction::AdvanceNonceAccount(address(0)?)),
+
+                SYSTEM_WITHDRAW_NONCE_ACCOUNT => Some(KnownInstruction::WithdrawNonceAccount(u64_at(0)?, address(1)?)),
+
+                SYSTEM_TRANSFER_WITH_SEED => Some(KnownInstruction::Transfer(u64_at(0)?, address(2)?)),
+
+                _ => None
+            }
+        }
+        else if (self.program_address == TOKEN_PROGRAM_ADDRESS) ||
+            (self.program_address == TOKEN_2022_PROGRAM_ADDRESS)
+        {
+            let (tag, rest) = self.data.split_first()?;
+            let amount = || Some(u64::from_le_bytes(rest.get(0..8)?.try_into().ok()?));
+            let address = |index : usize| Some(self.addresses.get(index)?.0.clone());
+            // The destination of a checked transfer, and the delegate of a checked approve, follow the source account
+            // and the mint
+            match *tag {
+                TOKEN_TRANSFER => Some(KnownInstruction::TokenTransfer(amount()?, address(1)?)),
+
+                TOKEN_TRANSFER_CHECKED => Some(KnownInstruction::TokenTransfer(amount()?, address(2)?)),
+
+                TOKEN_APPROVE => Some(KnownInstruction::TokenApprove(amount()?, address(1)?)),
+
+                TOKEN_APPROVE_CHECKED => Some(KnownInstruction::TokenApprove(amount()?, address(2)?)),
+
+                TOKEN_CLOSE_ACCOUNT => Some(KnownInstruction::TokenCloseAccount(address(0)?, address(1)?)),
+
+                // The authority type is followed by an optional new authority
+                TOKEN_SET_AUTHORITY => match rest.get(1)? {
+                    0 => Some(KnownInstruction::TokenSetAuthority(None)),
+                    1 => Some(KnownInstruction::TokenSetAuthority(Some(Address(rest.get(2..34)?.try_into().ok()?)))),
+                    _ => None
+                },
+
+                _ => None
+            }
+        }
+        else {
+            None
+        }
+    }
+
+    // The decimals of the mint of a token transfer, which only TransferChecked gives, following its amount
+    pub fn token_decimals(&self) -> Option<u8>
+    {
+        if ((self.program_address == TOKEN_PROGRAM_ADDRESS) || (self.program_address == TOKEN_2022_PROGRAM_ADDRESS)) &&
+            (self.data.first() == Some(&TOKEN_TRANSFER_CHECKED))
+        {
+            self.data.get(9).copied()
+        }
+        else {
+            None
+        }
+    }
+}
+
+fn main()
+{
+    let options = Options::from_args(std::env::args().skip(1));
+
+    let no_prompt = options.no_prompt;
+
+    if let Some(path) = &options.generate {
+        generate_key_file(path);
+        return;
+    }
+
+    if options.self_test {
+        std::process::exit(if self_test() { 0 } else { -1 });
+    }
+
+    // Listing addresses only decodes transactions, so no keys are needed
+    if options.addresses {
+        for_each_transaction(no_prompt, |decoded_tx| print_addresses(&decoded_tx));
+        return;
+    }
+
+    if options.addresses_csv {
+        for_each_transaction(no_prompt, |decoded_tx| print_addresses_csv(&decoded_tx));
+        return;
+    }
+
+    // And for listing instructions
+    if options.list_instructions {
+        for_each_transaction(no_prompt, |decoded_tx| print_instructions_list(&decoded_tx));
+        return;
+    }
+
+    // And for inspecting lookup tables, which are not transactions at all
+    if options.inspect {
+        inspect_lookup_tables(no_prompt);
+        return;
+    }
+
+    // And for dumping the bytes of transactions, which may not even decode
+    if options.dump_raw {
+        dump_raw_transactions(no_prompt);
+        return;
+    }
+
+    // Likewise for decoding to JSON
+    if options.decode_json {
+        for_each_transaction(no_prompt, |decoded_tx| {
+            println!("{}", serde_json::to_string_pretty(&decoded_tx.to_json()).unwrap_or_default())
+        });
+        return;
+    }
+
+    // And for writing out the message of each transaction, to be signed elsewhere
+    if options.emit_message {
+        for_each_transaction(no_prompt, |decoded_tx| {
+            let mut message = vec![];
+            match decoded_tx.message(&mut message) {
+                Ok(()) => println!("{}", base64::encode(&message)),
+                Err(e) => eprintln!("\n  {}", color_err(&e.to_string(), COLOR_RED))
+            }
+        });
+        return;
+    }
+
+    // And for removing the signatures from transactions
+    if options.strip_signatures {
+        for_each_transaction(no_prompt, |mut decoded_tx| {
+            decoded_tx.strip_signatures();
+            let mut encoded_tx = vec![];
+            match decoded_tx.encode(&mut encoded_tx) {
+                Ok(()) => {
+                    println!("\n  Unsigned transaction:\n");
+                    print_base64(&encoded_tx, &options);
+                },
+                Err(e) => eprintln!("\n  {}", color_err(&e.to_string(), COLOR_RED))
+            }
+        });
+        return;
+    }
+
+    // And for merging the signatures of copies of a transaction
+    if options.merge {
+        merge_transactions(&options);
+        return;
+    }
+
+    // And for encoding transactions described in JSON
+    if options.encode_json {
+        encode_json_transactions(&options);
+        return;
+    }
+
+    // And for building a transfer
+    if let Some((from, to, lamports)) = &options.build_transfer {
+        let Some(blockhash) = &options.blockhash
+        else {
+            eprintln!("\n  {}\n", color_err("--build-transfer requires --blockhash", COLOR_RED));
+            std::process::exit(1);
+        };
+        println!("\n  Transfer transaction:\n");
+        print_base64(&build_transfer(from, to, *lamports, blockhash), &options);
+        println!();
+        return;
+    }
+
+    // This is a map from public key to key
+    let mut keys = HashMap::<Pubkey, ed25519_dalek::Keypair>::new();
+
+    let mut keys_in_order = Vec::<Pubkey>::new();
+
+    #[cfg(not(feature = "keyring"))]
+    if options.keyring.is_some() {
+        eprintln!("\n  {}\n", color_err("--keyring requires solsign built with the keyring feature", COLOR_RED));
+        std::process::exit(1);
+    }
+
+    // The keys of an existing keyring are loaded first.  A keyring that doesn't exist yet is written with all of the
+    // session's keys once they have been loaded.
+    #[cfg(feature = "keyring")]
+    let new_keyring = match &options.keyring {
+        Some(path) if std::path::Path::new(path).exists() => {
+            for dalek_keypair in open_keyring(path) {
+                let public_key = Pubkey(dalek_keypair.public.to_bytes());
+
+                if keys.insert(public_key.clone(), dalek_keypair).is_none() {
+                    keys_in_order.push(public_key);
+                }
+            }
+            None
+        },
+        new_keyring => new_keyring.as_ref()
+    };
+
+    for key_file in &options.key_files {
+        let key_file = expand_path(key_file).unwrap_or_else(|e| {
+            eprintln!("\n{}\n", color_err(&format!("ERROR: Invalid key file path {}: {}", key_file, e), COLOR_RED));
+            std::process::exit(-1);
+        });
+
+        // A directory supplies every .json file within it as a key file
+        let paths = if key_file.is_dir() { key_files_in_directory(&key_file) } else { vec![key_file] };
+
+        for path in paths {
+            let dalek_keypair = read_key_file(&path);
+
+            let public_key = Pubkey(dalek_keypair.public.to_bytes());
+
+            if keys.insert(public_key.clone(), dalek_keypair).is_none() {
+                keys_in_order.push(public_key);
+            }
+        }
+    }
+
+    // Seeds given on the command line are used as-is, or with the derivation path if one was given
+    for seed in &options.seeds {
+        let dalek_keypair = match &options.derivation_path {
+            Some(derivation_path) => keypair_from_seed_and_derivation_path(seed, derivation_path.clone()),
+            None => keypair_from_seed(seed)
+        }
+        .unwrap_or_else(|e| {
+            eprintln!("\n{}\n", color_err(&e, COLOR_RED));
+            std::process::exit(-1);
+        });
+
+        let public_key = Pubkey(dalek_keypair.public.to_bytes());
+
+        if keys.insert(public_key.clone(), dalek_keypair).is_none() {
+            keys_in_order.push(public_key);
+        }
+    }
+
+    // Each mnemonic given in a file derives exactly one key, so there is no choosing between derived keys as there is
+    // when a mnemonic is entered at the prompt; the derivation path is what chooses
+    if let Some(mnemonic_file) = &options.mnemonic_file {
+        // Spaces may be part of a passphrase, so only the line ending is removed
+        let passphrase = options.passphrase_file.as_ref().map(|path| read_secret_file(path)).unwrap_or_default();
+        let passphrase =
+            passphrase.strip_suffix('\n').map(|p| p.strip_suffix('\r').unwrap_or(p)).unwrap_or(&passphrase);
+
+        for dalek_keypair in
+            read_mnemonic_file(mnemonic_file, passphrase, options.derivation_path.as_ref(), options.assume_yes)
+        {
+            let public_key = Pubkey(dalek_keypair.public.to_bytes());
+
+            if keys.insert(public_key.clone(), dalek_keypair).is_none() {
+                keys_in_order.push(public_key);
+            }
+        }
+    }
+    else if options.passphrase_file.is_some() {
+        eprintln!("\n  {}\n", color_err("--passphrase-file requires --mnemonic-file", COLOR_RED));
+        std::process::exit(1);
+    }
+
+    // Keys given on stdin ahead of the transactions, for sessions driven by a script, which would otherwise have to
+    // respond to the key prompts
+    if options.keys_from_stdin {
+        for dalek_keypair in read_keys_from_stdin(options.derivation_path.as_ref(), options.assume_yes) {
+            let public_key = Pubkey(dalek_keypair.public.to_bytes());
+
+            if keys.insert(public_key.clone(), dalek_keypair).is_none() {
+                keys_in_order.push(public_key);
+            }
+        }
+    }
+
+    // If no-prompt or no-key-prompt, don't read keys in from stdin.  With no-key-prompt, keys come only from key files
+    // but the challenge password is still asked for, which suits a human approving transactions supplied by a script.
+    // With --binary or --concatenated, stdin carries only transactions, so keys can't be read from it either.
+    if !no_prompt && !options.no_key_prompt && !options.binary && !options.concatenated && !options.keys_from_stdin {
+        loop {
+            println!("\n  Public keys provided thus far:\n");
+
+            if keys_in_order.is_empty() {
+                println!("    None");
+            }
+            else {
+                for key in &keys_in_order {
+                    println!("    {}", key);
+                }
+            }
+
+            let mnemonic = prompt_password("\n  Enter mnemonic seed words of next key, or press ENTER to continue: ")
+                .unwrap_or_else(|_| exit_at_end_of_input());
+
+            if mnemonic.is_empty() {
+                break;
+            }
+
+            let mnemonic = mnemonic.trim();
+
+            // A mistyped mnemonic still produces keys, just not the intended ones, so check it before going further.
+            // Showing the word count helps to catch a dropped or repeated word.
+            let word_count = mnemonic.split_whitespace().count();
+            match validate_mnemonic(mnemonic) {
+                Ok(()) => println!(
+                    "\n  {}",
+                    color_out(&format!("Mnemonic has {} words and a valid checksum.", word_count), COLOR_GREEN)
+                ),
+
+                Err(e) => {
+                    println!("\n  Mnemonic has {} words.", word_count);
+                    print_warning("invalid-mnemonic", &e);
+                    if options.assume_yes {
+                        println!("\n  Using it anyway, as --assume-yes was given.");
+                    }
+                    else if !ask_use_invalid_mnemonic() {
+                        continue;
+                    }
+                }
+            }
+
+            let passphrase = prompt_password("\n  Enter passphrase seed, or press ENTER for no passphrase: ")
+                .unwrap_or_else(|_| exit_at_end_of_input());
+
+            // Deriving the seed and then the keys can take long enough on a slow computer to look like a hang, so say
+            // what is happening until the derived keys are shown.  This is only for someone watching a terminal.
+            let deriving_status = "  Deriving keys...";
+            let show_deriving_status = std::io::stdout().is_terminal();
+            if show_deriving_status {
+                print!("{}", deriving_status);
+                let _ = std::io::stdout().flush();
+            }
+
+            let seed = generate_seed_from_seed_phrase_and_passphrase(mnemonic, &passphrase);
+
+            // Now derive keypairs directly, and with derivation path m/44'/501'/0'/0' through m/44'/501'/0'/9', to
+            // cover all expected possible sources of mnemonics and passphrases (i.e. solana-keygen plus standard
+            // wallets).  Then let the user choose which was their key (or none!).
+            let mut keypairs = Vec::<(String, ed25519_dalek::Keypair)>::new();
+
+            keypairs.push((
+                "                ".to_string(),
+                keypair_from_seed(&seed).unwrap_or_else(|e| {
+                    eprintln!("\n{}\n", color_err(&e, COLOR_RED));
+                    std::process::exit(-1);
+                })
+            ));
+
+            for i in 0..9 {
+                let path = vec![
+                    derivation_path::ChildIndex::Hardened(44),
+                    derivation_path::ChildIndex::Hardened(501),
+                    derivation_path::ChildIndex::Hardened(0),
+                    derivation_path::ChildIndex::Hardened(i)
+                ];
+                let derivation_path = derivation_path::DerivationPath::new(&*path);
+                keypairs.push((
+                    format!("m/44'/501'/0'/{}'", i),
+                    keypair_from_seed_and_derivation_path(&seed, derivation_path).unwrap_or_else(|e| {
+                        eprintln!("\n{}\n", color_err(&e, COLOR_RED));
+                        std::process::exit(-1);
+                    })
+                ));
+            }
+
+            if show_deriving_status {
+                print!("\r{}\r", " ".repeat(deriving_status.len()));
+            }
+
+            // The derived keys whose public keys start with --pubkey-prefix
+            let matching : Vec<usize> = match &options.pubkey_prefix {
+                Some(prefix) => (0..keypairs.len())
+                    .filter(|i| Pubkey(keypairs[*i].1.public.to_bytes()).to_string().starts_with(prefix.as_str()))
+                    .collect(),
+                None => vec![]
+            };
+
+            if options.pubkey_prefix.is_some() && matching.is_empty() {
+                print_warning("no-prefix-match", "No derived key matches --pubkey-prefix.");
+            }
+
+            loop {
+                println!("\n  Derived Keys:\n");
+
+                for (i, kp) in keypairs.iter().enumerate() {
+                    let padding = if i > 9 { " ".to_string() } else { "  ".to_string() };
+                    let pubkey = bs58::encode(kp.1.public.to_bytes()).into_string();
+                    let pubkey = if matching.contains(&i) {
+                        color_out(&format!("{} (match)", pubkey), COLOR_GREEN)
+                    }
+                    else {
+                        pubkey
+                    };
+                    println!("   ({}){}{}    {}", i, padding, kp.0, pubkey);
+                }
+
+                // A single match is the key that was asked for, so there's nothing to choose
+                let line = if let [selection] = matching[..] {
+                    println!("\n  Selected derived key {}, which matches --pubkey-prefix.", selection);
+                    selection.to_string()
+                }
+                else {
+                    print!("\n  Select a derived key 0 - 9 from above, or press ENTER to skip: ");
+                    let _ = std::io::stdout().flush();
+
+                    read_stdin_line().unwrap_or_else(|| exit_at_end_of_input())
+                };
+
+                let line = line.replace("\n", "").replace("\r", "");
+
+                if line.is_empty() {
+                    break;
+                }
+
+                if let Ok(selection) = line.parse::<u8>().map(|s| s as usize) {
+                    if selection < keypairs.len() {
+                        let kp = keypairs.remove(selection).1;
+                        let public_key = Pubkey(kp.public.to_bytes());
+                        if keys.insert(public_key.clone(), kp).is_none() {
+                            keys_in_order.push(public_key);
+                        }
+                        break;
+                    }
+                    else {
+                        println!("\n\n  {}\n", color_out("Invalid selection, try again.", COLOR_RED));
+                    }
+                }
+                else {
+                    println!("\n\n  {}\n", color_out("Invalid selection, try again.", COLOR_RED));
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "keyring")]
+    if let Some(path) = new_keyring {
+        if !keys_in_order.is_empty() {
+            create_keyring(path, &keys_in_order.iter().map(|pubkey| &keys[pubkey]).collect::<Vec<_>>());
+        }
+    }
+
+    // Only the public keys are printed, so that scripts can read them; none at all is not an error, as that is how a
+    // script finds that a key it needs is not available
+    if options.show_pubkeys {
+        keys_in_order.iter().for_each(|pubkey| println!("{}", pubkey));
+        return;
+    }
+
+    // Nothing but transactions may be written to stdout with --binary
+    if !options.binary {
+        println!();
+    }
+
+    // Signatures given with --signature are enough to work with even without any keys, except when signing messages or
+    // listing associated token accounts
+    if keys_in_order.is_empty() && (options.sign_message || options.ata_mint.is_some() || options.signatures.is_empty())
+    {
+        eprintln!("  {}\n", color_err("No keys provided, cannot sign.  Exiting.", COLOR_RED));
+        std::process::exit(-1);
+    }
+
+    if let Some(mint) = &options.ata_mint {
+        print_associated_token_addresses(&keys_in_order, mint);
+        return;
+    }
+
+    // Allow the user to provide a password that will be used to challenge them before each transaction is signed.
+    // This improves security - in case the user steps away from their computer, no one else can sign transactions if
+    // they don't know the password.  --no-challenge skips this without disabling the other prompts.
+    let password = if no_prompt || options.no_challenge {
+        "".to_string()
+    }
+    else if let Some(password_fd) = options.password_fd {
+        read_password_fd(password_fd)
+    }
+    else {
+        prompt_password(
+            "  Enter a password to be challenged with before each transaction is signed\n  or press ENTER for no \
+             signing challenge password: "
+        )
+        .unwrap_or_else(|_| exit_at_end_of_input())
+    };
+
+    if options.sign_message {
+        sign_messages(&keys, &keys_in_order, &password, no_prompt);
+        return;
+    }
+
+    if options.binary {
+        sign_binary_transactions(&mut std::io::stdin().lock(), &keys, &options, &password);
+        return;
+    }
+
+    if options.concatenated {
+        sign_concatenated_transactions(&keys, &options, &password);
+        return;
+    }
+
+    // The keys that have signed anything during the session
+    let mut used_keys = HashSet::<Pubkey>::new();
+
+    // The hashes of the messages of the transactions read during the session, to warn of duplicates
+    let mut read_messages = HashSet::<Sha256Digest>::new();
+
+    loop {
+        println!("\n  Enter Base64 encoded transaction:\n");
+
+        match read_input() {
+            Some(Input::Transaction(decoded_tx)) => {
+                sign_transaction(decoded_tx, &keys, &options, &password, &mut used_keys, &mut read_messages)
+            },
+
+            Some(Input::Command(command)) => match command.as_str() {
+                ":remove" => remove_key(&mut keys, &mut keys_in_order),
+
+                _ => print_unknown_command(&command)
+            },
+
+            Some(Input::End) => {
+                // Keys that were never needed only added exposure, and may mean that a transaction that was expected
+                // to need one of them did not.  This is only for the user of an interactive session.
+                let unused_keys : Vec<&Pubkey> =
+                    keys_in_order.iter().filter(|pubkey| !used_keys.contains(pubkey)).collect();
+                if !no_prompt && !unused_keys.is_empty() {
+                    print_warning("unused-keys", "These keys were loaded but never used to sign anything:");
+                    unused_keys
+                        .iter()
+                        .for_each(|pubkey| eprintln!("\n    {}", color_err(&pubkey.to_string(), COLOR_YELLOW)));
+                }
+                exit_at_end_of_input();
+            },
+
+            None => ()
+        }
+
+        // no_prompt stops after the first transaction
+        if no_prompt {
+            println!();
+            break;
+        }
+    }
+}
+
+impl std::fmt::Display for Pubkey
+{
+    fn fmt(
+        &self,
+        f : &mut std::fmt::Formatter
+    ) -> std::fmt::Result
+    {
+        write!(f, "{}", bs58::encode(self.0).into_string())
+    }
+}
+
+impl std::str::FromStr for Pubkey
+{
+    type Err = String;
+
+    fn from_str(s : &str) -> Result<Self, Self::Err>
+    {
+        decode_base58_pubkey(s).map(Pubkey)
+    }
+}
+
+impl std::fmt::Display for KnownInstruction
+{
+    fn fmt(
+        &self,
+        f : &mut std::fmt::Formatter
+    ) -> std::fmt::Result
+    {
+        match self {
+            KnownInstruction::SetComputeUnitLimit(units) => write!(f, "Set compute unit limit to {} units", units),
+
+            KnownInstruction::SetComputeUnitPrice(price) => {
+                write!(f, "Set compute unit price to {} micro-lamports per unit", price)
+            },
+
+            KnownInstruction::Transfer(lamports, destination) => {
+                write!(f, "Transfer {} lamports to {}", lamports, destination)
+            },
+
+            KnownInstruction::CreateAccount(lamports, account) => {
+                write!(f, "Create account {} with {} lamports", account, lamports)
+            },
+
+            KnownInstruction::AdvanceNonceAccount(nonce_account) => {
+                write!(f, "Advance nonce account {}", nonce_account)
+            },
+
+            KnownInstruction::WithdrawNonceAccount(lamports, destination) => {
+                write!(f, "Withdraw {} lamports from nonce account to {}", lamports, destination)
+            },
+
+            KnownInstruction::TokenTransfer(amount, destination) => {
+                write!(f, "Transfer {} token units to {}", amount, destination)
+            },
+
+            KnownInstruction::TokenApprove(amount, delegate) => {
+                write!(f, "Approve {} to transfer {} token units", delegate, amount)
+            },
+
+            KnownInstruction::TokenCloseAccount(account, destination) => {
+                write!(f, "Close token account {}, sending its lamports to {}", account, destination)
+            },
+
+            KnownInstruction::TokenSetAuthority(Some(authority)) => write!(f, "Set authority to {}", authority),
+
+            KnownInstruction::TokenSetAuthority(None) => write!(f, "Remove authority")
+        }
+    }
+}
+
+impl std::fmt::Display for TxError
+{
+    fn fmt(
+        &self,
+        f : &mut std::fmt::Formatter
+    ) -> std::fmt::Result
+    {
+        match self {
+            TxError::Truncated => write!(f, "Transaction is truncated"),
+
+            TxError::Io(e) => write!(f, "{}", e),
+
+            TxError::InvalidSignature { signer, error } => {
+                write!(f, "Signer {} has an invalid signature: {}", signer, error)
+            },
+
+            TxError::InvalidCompactU16(bytes) => {
+                let len = bytes.iter().position(|b| (b & 0x80) == 0).map(|i| i + 1).unwrap_or(bytes.len());
+                write!(f, "Invalid compact-u16 encoding: {}", hex(&bytes[..len]))
+            },
+
+            TxError::TooManySignatures { maximum, actual } => {
+                write!(f, "Too many signatures: expected at most {}, got {}", maximum, actual)
+            },
+
+            TxError::TooManyReadOnlySigners { maximum, actual } => {
+                write!(f, "Too many signed read only addresses: expected at most {}, got {}", maximum, actual)
+            },
+
+            TxError::MissingFeePayer => write!(f, "Minimum signed address count of 1 required for fee payer"),
+
+            TxError::DuplicateSigner(signer) => {
+                write!(f, "Signer {} appears more than once among signers of the same permissions", signer)
+            },
+
+            TxError::TooManyAddresses { maximum, actual } => {
+                write!(f, "Too many addresses: expected at most {}, got {}", maximum, actual)
+            },
+
+            TxError::TooFewAddresses { minimum, actual } => {
+                write!(f, "Too few addresses in header; {} supplied but at least {} required", actual, minimum)
+            },
+
+            TxError::TooManyInstructions { maximum, actual } => {
+                write!(f, "Too many instructions: expected at most {}, got {}", maximum, actual)
+            },
 
-        if uindex < self.signed_read_only_addresses.len() {
-            return Some((Address(self.signed_read_only_addresses[uindex].pubkey.0), true, false));
-        }
+            TxError::TooManyInstructionAddresses { instruction, maximum, actual } => write!(
+                f,
+                "Too many addresses in instruction {}: expected at most {} got {}",
+                instruction, maximum, actual
+            ),
 
-        uindex -= self.signed_read_only_addresses.len();
+            TxError::TooMuchInstructionData { instruction, maximum, actual } => write!(
+                f,
+                "Too many data bytes in instruction {}: expected at most {} got {}",
+                instruction, maximum, actual
+            ),
 
-        if uindex < self.unsigned_read_write_addresses.len() {
-            return Some((Address(self.unsigned_read_write_addresses[uindex].0), false, true));
-        }
+            TxError::InvalidProgramIndex { instruction, index } => {
+                write!(f, "Invalid program id index {} for instruction {}", index, instruction)
+            },
 
-        uindex -= self.unsigned_read_write_addresses.len();
+            TxError::InvalidAddressIndex { instruction, index } => {
+                write!(f, "Invalid address index {} referenced from instruction {}", index, instruction)
+            },
 
-        if uindex < self.unsigned_read_only_addresses.len() {
-            return Some((Address(self.unsigned_read_only_addresses[uindex].0), false, false));
-        }
+            TxError::AddressNotFound(address) => {
+                write!(f, "Invalid Transaction - address {} is not in address list", address)
+            },
 
-        None
+            TxError::AtOffset { offset, error } => write!(f, "{} (detected at byte offset {})", error, offset)
+        }
     }
+}
 
-    fn encode_compact_u16(
-        mut u : u16,
-        w : &mut dyn std::io::Write
-    ) -> Result<(), String>
+impl std::error::Error for TxError
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)>
     {
-        let mut buf = [0_u8; 3];
+        match self {
+            TxError::Io(e) => Some(e),
 
-        let mut v = (u & 0x7F) as u8;
-        if u > 0x7F {
-            buf[0] = v | 0x80;
-            u >>= 7;
-            v = (u & 0x7F) as u8;
-            if u > 0x7F {
-                buf[1] = v | 0x80;
-                buf[2] = (u >> 7) as u8;
-                Self::write(w, &buf)
-            }
-            else {
-                buf[1] = v;
-                Self::write(w, &buf[0..2])
-            }
-        }
-        else {
-            buf[0] = v;
-            Self::write(w, &buf[0..1])
+            TxError::InvalidSignature { error, .. } => Some(error),
+
+            TxError::AtOffset { error, .. } => Some(error.as_ref()),
+
+            _ => None
         }
     }
+}
 
-    fn encode_signature(
-        signature : Option<ed25519_dalek::Signature>,
-        w : &mut dyn std::io::Write
-    ) -> Result<(), String>
+impl std::fmt::Display for Address
+{
+    fn fmt(
+        &self,
+        f : &mut std::fmt::Formatter
+    ) -> std::fmt::Result
     {
-        Self::write(w, signature.map(|s| s.to_bytes()).unwrap_or(EMPTY_SIGNATURE_BYTES).as_slice())
+        write!(f, "{}", bs58::encode(self.0).into_string())
     }
+}
 
-    fn read(
-        r : &mut dyn std::io::Read,
-        buf : &mut [u8]
-    ) -> Result<(), Option<String>>
-    {
-        match r.read_exact(buf) {
-            Ok(_) => Ok(()),
-
-            Err(e) => match e.kind() {
-                std::io::ErrorKind::UnexpectedEof => Err(None),
+impl std::str::FromStr for Address
+{
+    type Err = String;
 
-                _ => Err(Some(e.to_string()))
-            }
-        }
+    fn from_str(s : &str) -> Result<Self, Self::Err>
+    {
+        decode_base58_pubkey(s).map(Address)
     }
+}
 
-    fn write(
-        w : &mut dyn std::io::Write,
-        buf : &[u8]
-    ) -> Result<(), String>
+impl PartialEq<Pubkey> for Address
+{
+    fn eq(
+        &self,
+        other : &Pubkey
+    ) -> bool
     {
-        w.write_all(&buf).map(|_| ()).map_err(|e| format!("{}", e))
+        self.0 == other.0
     }
 }
 
-fn main()
+#[cfg(test)]
+mod tests
 {
-    let mut no_prompt = false;
-
-    // This is a map from base-58 encoded public key to key
-    let mut keys = HashMap::<String, ed25519_dalek::Keypair>::new();
+    use super::*;
 
-    let mut keys_in_order = Vec::<String>::new();
+    fn test_keypair(seed : u8) -> ed25519_dalek::Keypair
+    {
+        keypair_from_seed(&[seed; 32]).unwrap()
+    }
 
-    let mut key_files = Vec::<String>::new();
+    fn unsigned(pubkey : &Pubkey) -> PubkeyWithSignature
+    {
+        PubkeyWithSignature { pubkey : pubkey.clone(), signature : None }
+    }
 
-    key_files.extend(std::env::args().skip(1));
+    // Base64 encoded legacy transactions, each with recent blockhash sha256("solsign"), and signers whose keys are
+    // test_keypair(1), test_keypair(2) and test_keypair(3).  Token accounts and mints are the sha256 of their names.
+
+    // A System transfer of 1000000 lamports from the key of seed 1 to the key of seed 2, not yet signed
+    const UNSIGNED_TRANSFER : &str = concat!(
+        "AQAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAABAAEDiojj",
+        "3XQJ8ZX9UtstPLpdcspnCb8dlBIb83SIAbQPb1yBOXcOqH0XX1ajVGbDTH7My42KkbTuN6Jd9g9bj8mzlAAAAAAAAAAAAAAA",
+        "AAAAAAAAAAAAAAAAAAAAAAAAAAAAj8CBClxvDd7z6YkAqtOMKEycMWI6yhYTlj6ujoB+bUYBAgIAAQwCAAAAQEIPAAAAAAA="
+    );
+
+    // Compute unit limit and price instructions followed by a System transfer, signed by the key of seed 1
+    const SIGNED_COMPUTE_BUDGET_TRANSFER : &str = concat!(
+        "ARIw0GtngYpD8OT/pZ9SdFmHiopncJeWnOhR4gk3/Cu4HMfzDMUzFO2yfi5ThnejTdFOcdo9/OhYKPBlcJBd4goBAAIEiojj",
+        "3XQJ8ZX9UtstPLpdcspnCb8dlBIb83SIAbQPb1yBOXcOqH0XX1ajVGbDTH7My42KkbTuN6Jd9g9bj8mzlAAAAAAAAAAAAAAA",
+        "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAwZGb+UhFzL/7K26csOb57yM5bvF9xJrLEObOkAAAACPwIEKXG8N3vPpiQCq04woTJwx",
+        "YjrKFhOWPq6OgH5tRgMDAAUCQA0DAAMACQOIEwAAAAAAAAICAAEMAgAAAICy5g4AAAAA"
+    );
+
+    // Two System transfers needing the keys of seeds 1 and 2 as read-write signers and of seed 3 as a read-only
+    // signer, signed only by the key of seed 2
+    const PARTIALLY_SIGNED_MULTI_SIGNER : &str = concat!(
+        "AwAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAABRX6X6zjT9",
+        "JQpWhnW8f07MwpwfrcX/R8V4sQ4p36LIOSDpihEVSa40LRsnaOO1rBOJdJ8pPPkw+738kVju0QQEAAAAAAAAAAAAAAAAAAAA",
+        "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAMBAQSKiOPddAnxlf1S2y08ul1yymcJ",
+        "vx2UEhvzdIgBtA9vXIE5dw6ofRdfVqNUZsNMfszLjYqRtO43ol32D1uPybOU7UkoxijRwsbq6QM4kFmVYSlZJzpcY/k2NsFG",
+        "FKyHN9EAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAI/AgQpcbw3e8+mJAKrTjChMnDFiOsoWE5Y+ro6Afm1GAgMC",
+        "AAEMAgAAAAUAAAAAAAAAAwICAAwCAAAABwAAAAAAAAA="
+    );
+
+    // A token program TransferChecked of 1500000 with 6 decimals, authorized by the key of seed 1, not yet signed
+    const UNSIGNED_TOKEN_TRANSFER_CHECKED : &str = concat!(
+        "AQAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAABAAIFiojj",
+        "3XQJ8ZX9UtstPLpdcspnCb8dlBIb83SIAbQPb1wlpmNCY8Gx9vxGl6BOK5kE6ksEKomvWdyT7B9dRISKJgYYAT+mSsaAe96i",
+        "ErvdCP/GKN1ED6cluSqLU0qELzPp3G8Xu+yCT/+Phlh5ZrIEfbarc2eFhAFR8T0dqxJOKlQG3fbh12Whk9nL4UbO63msHLSF",
+        "7V9bN5E6jPWFfv8AqY/AgQpcbw3e8+mJAKrTjChMnDFiOsoWE5Y+ro6Afm1GAQQEAQMCAAoMYOMWAAAAAAAG"
+    );
+
+    const VECTORS : [&str; 4] = [
+        UNSIGNED_TRANSFER,
+        SIGNED_COMPUTE_BUDGET_TRANSFER,
+        PARTIALLY_SIGNED_MULTI_SIGNER,
+        UNSIGNED_TOKEN_TRANSFER_CHECKED
+    ];
+
+    fn decode_vector(vector : &str) -> (Vec<u8>, Transaction)
+    {
+        let bytes = base64::decode(vector).unwrap();
+        let tx = Transaction::decode(&mut bytes.as_slice()).unwrap();
+        (bytes, tx)
+    }
 
-    if key_files.len() > 0 {
-        match key_files[0].as_str() {
-            "--help" => {
-                println!("{}", usage_string());
-                std::process::exit(0);
-            },
+    fn test_pubkey(seed : u8) -> Pubkey
+    {
+        Pubkey(test_keypair(seed).public.to_bytes())
+    }
 
-            "--no-prompt" => {
-                no_prompt = true;
-                key_files.remove(0);
-            },
+    // Checks every signature present in the transaction against its message
+    fn assert_signatures_valid(tx : &Transaction)
+    {
+        let mut message = vec![];
+        tx.message(&mut message).unwrap();
 
-            _ => ()
+        for slot in tx.signed_read_write_addresses.iter().chain(tx.signed_read_only_addresses.iter()) {
+            if let Some(signature) = slot.signature {
+                let public_key = ed25519_dalek::PublicKey::from_bytes(&slot.pubkey.0).unwrap();
+                assert!(public_key.verify_strict(&message, &signature).is_ok(), "invalid signature by {}", slot.pubkey);
+            }
         }
     }
 
-    for key_file in key_files {
-        let contents : String = std::fs::read_to_string(&key_file).unwrap_or_else(|e| {
-            eprintln!("\nERROR: Failed to read key file {}: {}\n", key_file, e);
-            std::process::exit(-1);
-        });
+    #[test]
+    fn signer_in_both_signed_lists_signs_every_slot()
+    {
+        let keypair = test_keypair(1);
+        let pubkey = Pubkey(keypair.public.to_bytes());
+        let other = Pubkey(test_keypair(2).public.to_bytes());
+
+        // pubkey is both a read-write and a read-only signer, with another signer in between
+        let mut tx = Transaction {
+            signed_read_write_addresses : vec![unsigned(&pubkey), unsigned(&other)],
+            signed_read_only_addresses : vec![unsigned(&pubkey)],
+            unsigned_read_write_addresses : vec![],
+            unsigned_read_only_addresses : vec![SYSTEM_PROGRAM_ADDRESS],
+            recent_blockhash : Some(Sha256Digest([1_u8; 32])),
+            instructions : vec![Instruction {
+                program_address : SYSTEM_PROGRAM_ADDRESS,
+                addresses : vec![(Address(pubkey.0), true, true), (Address(other.0), true, true)],
+                data : vec![]
+            }]
+        };
 
-        // Strip whitespace and [ and ], split by , and then parse bytes
-        let private_key_bytes : Vec<u8> = contents
-            .replace(" ", "")
-            .replace("[", "")
-            .replace("]", "")
-            .split(",")
-            .map(|s| {
-                u8::from_str_radix(s, 10).unwrap_or_else(|e| {
-                    eprintln!("\nERROR: Invalid key file value {}: {}\n", key_file, e);
-                    std::process::exit(-1);
-                })
-            })
-            .collect();
+        let needed : Vec<Pubkey> = tx.needed_signatures().collect();
+        assert_eq!(needed.len(), 2);
+        assert!(needed.contains(&pubkey));
+        assert!(needed.contains(&other));
 
-        let dalek_keypair = ed25519_dalek::Keypair::from_bytes(private_key_bytes.as_slice()).unwrap_or_else(|e| {
-            eprintln!("\nERROR: Invalid private key file {}: {}\n", key_file, e);
-            std::process::exit(-1);
-        });
+        let mut message = vec![];
+        tx.message(&mut message).unwrap();
+        tx.sign(&pubkey, keypair.sign(&message)).unwrap();
 
-        let public_key = bs58::encode(dalek_keypair.public.to_bytes()).into_string();
+        assert!(tx.signed_read_write_addresses[0].signature.is_some());
+        assert!(tx.signed_read_write_addresses[1].signature.is_none());
+        assert!(tx.signed_read_only_addresses[0].signature.is_some());
 
-        if keys.insert(public_key.clone(), dalek_keypair).is_none() {
-            keys_in_order.push(public_key);
+        let needed : Vec<Pubkey> = tx.needed_signatures().collect();
+        assert!(needed == vec![other]);
+
+        // The signature survives a round trip through the wire format in both slots
+        let mut encoded = vec![];
+        tx.encode(&mut encoded).unwrap();
+        let decoded = Transaction::decode(&mut encoded.as_slice()).unwrap();
+        for slot in [&decoded.signed_read_write_addresses[0], &decoded.signed_read_only_addresses[0]] {
+            assert!(keypair.public.verify_strict(&message, &slot.signature.unwrap()).is_ok());
         }
     }
 
-    // If no-prompt, don't read keys in from stdin
-    if !no_prompt {
-        loop {
-            println!("\n  Public keys provided thus far:\n");
-
-            if keys_in_order.len() == 0 {
-                println!("    None");
-            }
-            else {
-                for key in &keys_in_order {
-                    println!("    {}", key);
-                }
-            }
+    #[test]
+    fn account_at_spans_all_address_lists()
+    {
+        let tx = Transaction {
+            signed_read_write_addresses : vec![unsigned(&test_pubkey(1)), unsigned(&test_pubkey(2))],
+            signed_read_only_addresses : vec![unsigned(&test_pubkey(3))],
+            unsigned_read_write_addresses : vec![Address([4_u8; 32])],
+            unsigned_read_only_addresses : vec![Address([5_u8; 32]), SYSTEM_PROGRAM_ADDRESS],
+            recent_blockhash : Some(Sha256Digest([1_u8; 32])),
+            instructions : vec![]
+        };
 
-            let mnemonic =
-                rpassword::prompt_password("\n  Enter mnemonic seed words of next key, or press ENTER to continue: ")
-                    .unwrap_or_else(|_| {
-                        println!("\n");
-                        std::process::exit(0);
-                    });
+        let expected = [
+            (Address(test_pubkey(1).0), true, true),
+            (Address(test_pubkey(2).0), true, true),
+            (Address(test_pubkey(3).0), true, false),
+            (Address([4_u8; 32]), false, true),
+            (Address([5_u8; 32]), false, false),
+            (SYSTEM_PROGRAM_ADDRESS, false, false)
+        ];
+
+        for (index, (address, is_signer, is_writable)) in expected.into_iter().enumerate() {
+            assert_eq!(tx.account_at(index as u8), Some(AccountMeta { address, is_signer, is_writable }));
+        }
 
-            if mnemonic.len() == 0 {
-                break;
-            }
+        // The index just past the last account, and the largest index an instruction can give
+        assert_eq!(tx.account_at(6), None);
+        assert_eq!(tx.account_at(u8::MAX), None);
+    }
 
-            let mnemonic = mnemonic.trim();
+    #[test]
+    fn transfer_limits_apply_to_the_whole_transaction()
+    {
+        let payer = test_pubkey(1);
+        let destination = Address([4_u8; 32]);
+        let (source, other_source) = (Address([5_u8; 32]), Address([6_u8; 32]));
+
+        let system_transfer = |lamports : u64| Instruction {
+            program_address : SYSTEM_PROGRAM_ADDRESS,
+            addresses : vec![(Address(payer.0), true, true), (destination.clone(), false, true)],
+            data : [&SYSTEM_TRANSFER.to_le_bytes()[..], &lamports.to_le_bytes()].concat()
+        };
 
-            let passphrase =
-                rpassword::prompt_password("\n  Enter passphrase seed, or press ENTER for no passphrase: ")
-                    .unwrap_or_else(|_| {
-                        println!("\n");
-                        std::process::exit(0);
-                    });
+        let token_transfer = |source : &Address, amount : u64| Instruction {
+            program_address : TOKEN_PROGRAM_ADDRESS,
+            addresses : vec![
+                (source.clone(), false, true),
+                (destination.clone(), false, true),
+                (Address(payer.0), true, true)
+            ],
+            data : [&[TOKEN_TRANSFER][..], &amount.to_le_bytes()].concat()
+        };
 
-            let seed = generate_seed_from_seed_phrase_and_passphrase(&mnemonic, &passphrase);
+        let transaction = |instructions : Vec<Instruction>| Transaction {
+            signed_read_write_addresses : vec![unsigned(&payer)],
+            signed_read_only_addresses : vec![],
+            unsigned_read_write_addresses : vec![destination.clone(), source.clone(), other_source.clone()],
+            unsigned_read_only_addresses : vec![SYSTEM_PROGRAM_ADDRESS, TOKEN_PROGRAM_ADDRESS],
+            recent_blockhash : Some(Sha256Digest([1_u8; 32])),
+            instructions
+        };
 
-            // Now derive keypairs directly, and with derivation path m/44'/501'/0'/0' through m/44'/501'/0'/9', to
-            // cover all expected possible sources of mnemonics and passphrases (i.e. solana-keygen plus standard
-            // wallets).  Then let the user choose which was their key (or none!).
-            let mut keypairs = Vec::<(String, ed25519_dalek::Keypair)>::new();
+        let options = Options { max_lamports : Some(1000), max_token_amount : Some(1000), ..Options::default() };
 
-            keypairs.push((
-                "                ".to_string(),
-                keypair_from_seed(&seed).unwrap_or_else(|e| {
-                    eprintln!("\n{}\n", e);
-                    std::process::exit(-1);
-                })
-            ));
+        // Each transfer is within the limit, but together they are not
+        assert!(check_transfers(&transaction(vec![system_transfer(600)]), &options).is_ok());
+        assert!(check_transfers(&transaction(vec![system_transfer(600), system_transfer(600)]), &options).is_err());
 
-            for i in 0..9 {
-                let mut path = Vec::<derivation_path::ChildIndex>::new();
-                path.push(derivation_path::ChildIndex::Hardened(44));
-                path.push(derivation_path::ChildIndex::Hardened(501));
-                path.push(derivation_path::ChildIndex::Hardened(0));
-                path.push(derivation_path::ChildIndex::Hardened(i));
-                let derivation_path = derivation_path::DerivationPath::new(&*path);
-                keypairs.push((
-                    format!("m/44'/501'/0'/{}'", i),
-                    keypair_from_seed_and_derivation_path(&seed, derivation_path).unwrap_or_else(|e| {
-                        eprintln!("\n{}\n", e);
-                        std::process::exit(-1);
-                    })
-                ));
-            }
+        // Token amounts add up for the same source account, but not across different ones
+        assert!(check_transfers(
+            &transaction(vec![token_transfer(&source, 600), token_transfer(&source, 600)]),
+            &options
+        )
+        .is_err());
+        assert!(check_transfers(
+            &transaction(vec![token_transfer(&source, 600), token_transfer(&other_source, 600)]),
+            &options
+        )
+        .is_ok());
 
-            loop {
-                println!("\n  Derived Keys:\n");
+        // An instruction of the System program that can't be decoded can't be checked, so is refused
+        let mut allocate = system_transfer(0);
+        allocate.data = 8_u32.to_le_bytes().to_vec();
+        assert!(check_transfers(&transaction(vec![allocate.clone()]), &options).is_err());
+        assert!(check_transfers(&transaction(vec![allocate]), &Options::default()).is_ok());
+    }
 
-                for i in 0..keypairs.len() {
-                    let kp = &keypairs[i];
-                    let padding = if i > 9 { " ".to_string() } else { "  ".to_string() };
-                    println!("   ({}){}{}    {}", i, padding, kp.0, bs58::encode(kp.1.public.to_bytes()).into_string());
-                }
+    #[test]
+    fn key_files_with_windows_line_endings_parse()
+    {
+        let keypair = test_keypair(1);
+        let numbers : Vec<String> = keypair.to_bytes().iter().map(|b| b.to_string()).collect();
+
+        let cases = [
+            format!("[{}]", numbers.join(",")),
+            // As written by an editor on Windows, with a byte order mark and CRLF line endings
+            format!("\u{feff}[{}]\r\n", numbers.join(",")),
+            format!("[\r\n  {}\r\n]\r\n", numbers.join(",\r\n  ")),
+            format!("\u{feff}{}\r\n", bs58::encode(keypair.to_bytes()).into_string())
+        ];
+
+        for contents in cases {
+            assert_eq!(parse_key_file(&contents).unwrap().to_bytes(), keypair.to_bytes(), "parsing {:?}", contents);
+        }
 
-                print!("\n  Select a derived key 0 - 9 from above, or press ENTER to skip: ");
-                let _ = std::io::stdout().flush();
+        assert!(parse_key_file("[1,2,x]").is_err());
+    }
 
-                let mut line = "".to_string();
-                std::io::stdin().read_line(&mut line).unwrap_or_else(|_| {
-                    std::process::exit(0);
-                });
+    #[test]
+    fn transactions_with_windows_line_endings_decode()
+    {
+        for vector in VECTORS {
+            let (bytes, _) = decode_vector(vector);
 
-                if line.len() == 0 {
-                    println!("\n");
-                    std::process::exit(0);
-                }
+            // As pasted from a document with CRLF line endings, a byte order mark, and non-breaking spaces
+            let lines : Vec<&str> =
+                vector.as_bytes().chunks(76).map(|line| std::str::from_utf8(line).unwrap()).collect();
+            let mut pasted = format!("\u{feff}{}\r\n", lines.join("\u{a0}\r\n"));
 
-                let line = line.replace("\n", "").replace("\r", "");
+            pasted.retain(|c| !is_ignored_in_input(c));
+            assert_eq!(base64::decode(&pasted).unwrap(), bytes, "decoding {}", vector);
+        }
+    }
 
-                if line.len() == 0 {
-                    break;
-                }
+    #[test]
+    fn vectors_round_trip_byte_for_byte()
+    {
+        for vector in VECTORS {
+            let (bytes, tx) = decode_vector(vector);
 
-                if let Ok(selection) = u8::from_str_radix(&line, 10).map(|s| s as usize) {
-                    if selection < keypairs.len() {
-                        let kp = keypairs.remove(selection).1;
-                        let public_key = bs58::encode(kp.public.to_bytes()).into_string();
-                        if keys.insert(public_key.clone(), kp).is_none() {
-                            keys_in_order.push(public_key);
-                        }
-                        break;
-                    }
-                    else {
-                        println!("\n\n  Invalid selection, try again.\n");
-                    }
-                }
-                else {
-                    println!("\n\n  Invalid selection, try again.\n");
-                }
-            }
+            let mut encoded = vec![];
+            tx.encode(&mut encoded).unwrap();
+            assert_eq!(encoded, bytes, "re-encoding of {}", vector);
         }
     }
 
-    println!("");
-
-    if keys_in_order.len() == 0 {
-        eprintln!("  No keys provided, cannot sign.  Exiting.\n");
-        std::process::exit(-1);
+    #[test]
+    fn vectors_needed_signatures()
+    {
+        let cases = [
+            (UNSIGNED_TRANSFER, vec![test_pubkey(1)]),
+            (SIGNED_COMPUTE_BUDGET_TRANSFER, vec![]),
+            // Needed signatures are in the order of their Base58 encodings
+            (PARTIALLY_SIGNED_MULTI_SIGNER, vec![test_pubkey(1), test_pubkey(3)]),
+            (UNSIGNED_TOKEN_TRANSFER_CHECKED, vec![test_pubkey(1)])
+        ];
+
+        for (vector, expected) in cases {
+            let (_, tx) = decode_vector(vector);
+            assert_eq!(tx.needed_signatures().collect::<Vec<Pubkey>>(), expected, "needed by {}", vector);
+        }
     }
 
-    // Allow the user to provide a password that will be used to challenge them before each transaction is signed.
-    // This improves security - in case the user steps away from their computer, no one else can sign transactions if
-    // they don't know the password
-    let password = if no_prompt {
-        "".to_string()
+    #[test]
+    fn vectors_describe_known_instructions()
+    {
+        let cases = [
+            (UNSIGNED_TRANSFER, vec![format!("Transfer 1000000 lamports to {}", test_pubkey(2))]),
+            (SIGNED_COMPUTE_BUDGET_TRANSFER, vec![
+                "Set compute unit limit to 200000 units".to_string(),
+                "Set compute unit price to 5000 micro-lamports per unit".to_string(),
+                format!("Transfer 250000000 lamports to {}", test_pubkey(2))
+            ]),
+            (UNSIGNED_TOKEN_TRANSFER_CHECKED, vec![format!(
+                "Transfer 1500000 token units to {}",
+                Address(sha256(&[b"dst"]).0)
+            )])
+        ];
+
+        for (vector, expected) in cases {
+            let (_, tx) = decode_vector(vector);
+            let described : Vec<String> = tx
+                .instructions
+                .iter()
+                .map(|instruction| instruction.decode_known().map(|known| known.to_string()).unwrap_or_default())
+                .collect();
+            assert_eq!(described, expected, "instructions of {}", vector);
+        }
     }
-    else {
-        rpassword::prompt_password(
-            "  Enter a password to be challenged with before each transaction is signed\n  or press ENTER for no \
-             signing challenge password: "
-        )
-        .unwrap_or_else(|_| {
-            println!("\n");
-            std::process::exit(0);
-        })
-    };
 
-    loop {
-        println!("\n  Enter Base64 encoded transaction:\n");
+    #[test]
+    fn signing_vectors_produces_valid_signatures()
+    {
+        let keypairs = [test_keypair(1), test_keypair(2), test_keypair(3)];
 
-        // Read lines until a complete transaction is read in
-        let mut tx = "".to_string();
-        loop {
-            let mut line = "".to_string();
-            std::io::stdin().read_line(&mut line).unwrap_or_else(|_| {
-                println!("\n");
-                std::process::exit(0);
-            });
+        for vector in VECTORS {
+            let (_, mut tx) = decode_vector(vector);
 
-            if line.len() == 0 {
-                println!("\n");
-                std::process::exit(0);
-            }
+            // Signatures already present must be valid before any more are added
+            assert_signatures_valid(&tx);
 
-            line.retain(|c| !c.is_whitespace());
+            let mut message = vec![];
+            tx.message(&mut message).unwrap();
 
-            if line.len() > 0 {
-                tx.push_str(&line);
-            }
+            let signatures : Vec<(Pubkey, ed25519_dalek::Signature)> = tx
+                .needed_signatures()
+                .map(|pubkey| {
+                    let keypair = keypairs.iter().find(|keypair| keypair.public.to_bytes() == pubkey.0).unwrap();
+                    (pubkey, keypair.sign(&message))
+                })
+                .collect();
+            tx.sign_all(&signatures);
 
-            // Attempt a decode.  Might be short because not all lines of the transaction have been provided yet.
-
-            // Decode Base64
-            match base64::decode(&tx) {
-                Ok(bytes) => {
-                    match Transaction::decode(&mut bytes.as_slice()) {
-                        // If a completely decoded transaction was found, sign it
-                        Ok(mut decoded_tx) => {
-                            // Get the transaction to sign -- everything except the signatures
-                            let mut message = vec![];
-                            decoded_tx.message(&mut message).unwrap_or_else(|e| {
-                                eprintln!("\n{}\n", e);
-                                std::process::exit(-1);
-                            });
-
-                            if password.len() > 0 {
-                                println!("\n");
-                                let mut attempts = 0;
-                                loop {
-                                    let prompt = format!(
-                                        "  Enter challenge password ({} attempt{} remaining): ",
-                                        (5 - attempts),
-                                        if attempts == 4 { "" } else { "s" }
-                                    );
-                                    let password_attempt = rpassword::prompt_password(prompt).unwrap_or_else(|_| {
-                                        println!("\n");
-                                        std::process::exit(0);
-                                    });
-
-                                    if password_attempt == password {
-                                        println!("");
-                                        break;
-                                    }
-
-                                    if attempts == 4 {
-                                        println!("\n  Password challenge failed.\n");
-                                        std::process::exit(0);
-                                    }
-
-                                    attempts += 1;
-                                }
-                            }
+            assert_eq!(tx.needed_signatures().count(), 0, "still unsigned: {}", vector);
+            assert_signatures_valid(&tx);
 
-                            // For every signature incomplete within the transaction, add that signature if we
-                            // have the key, otherwise, put the bs58 encoded key in here.
-                            let mut unsigned = Vec::<String>::new();
-                            decoded_tx.needed_signatures().for_each(|pubkey| {
-                                let pubkey_string = pubkey.to_string();
-                                match keys.get(&pubkey_string) {
-                                    Some(keypair) => {
-                                        decoded_tx.sign(&pubkey, keypair.sign(&message)).unwrap_or_else(|e| {
-                                            eprintln!("\nFailed to sign with key {}: {}\n", pubkey_string, e);
-                                            std::process::exit(-1);
-                                        })
-                                    },
-                                    None => unsigned.push(pubkey_string)
-                                }
-                            });
-
-                            // Now output
-                            let mut encoded_tx = vec![];
-                            match decoded_tx.encode(&mut encoded_tx) {
-                                Ok(()) => {
-                                    // Now, if the transaction is completely signed, emit the signature
-                                    if unsigned.len() == 0 {
-                                        if let Some(signature) = decoded_tx.signed_read_write_addresses[0].signature {
-                                            println!("\n  Transaction is complete:\n");
-                                            print_base64(&encoded_tx);
-                                            println!(
-                                                "\n  Signature:\n\n   {}",
-                                                bs58::encode(signature.to_bytes()).into_string()
-                                            );
-                                        }
-                                    }
-                                    // Else, emit the partially signed tx
-                                    else {
-                                        println!("\n  Pubkeys still needed to sign:");
-                                        unsigned.iter().for_each(|pubkey| println!("\n    {}", pubkey));
-                                        println!("\n  Partially signed transaction:\n");
-                                        print_base64(&encoded_tx);
-                                    }
-                                },
-                                Err(e) => eprintln!("\n{}\n", e)
-                            }
+            // And remain so through the wire format
+            let mut encoded = vec![];
+            tx.encode(&mut encoded).unwrap();
+            assert_signatures_valid(&Transaction::decode(&mut encoded.as_slice()).unwrap());
+        }
+    }
 
-                            break;
-                        },
+    #[test]
+    fn compact_u16_boundary_values_round_trip()
+    {
+        let cases : [(u16, &[u8]); 6] = [
+            (0, &[0x00]),
+            (0x7F, &[0x7F]),
+            (0x80, &[0x80, 0x01]),
+            (0x3FFF, &[0xFF, 0x7F]),
+            (0x4000, &[0x80, 0x80, 0x01]),
+            (0xFFFF, &[0xFF, 0xFF, 0x03])
+        ];
+
+        for (value, bytes) in cases {
+            let mut encoded = vec![];
+            encode_compact_u16(value, &mut encoded).unwrap();
+            assert_eq!(encoded, bytes, "encoding of {:#x}", value);
+
+            let mut r = encoded.as_slice();
+            assert_eq!(decode_compact_u16(&mut r).unwrap(), value);
+            assert!(r.is_empty(), "decoding of {:#x} left bytes unread", value);
+        }
+    }
 
-                        // If an error occurred, then input was bad; break the loop to get the next tx
-                        Err(Some(_)) => break,
+    #[test]
+    fn compact_u16_rejects_non_canonical_encodings()
+    {
+        // Longer encodings of each value, whose canonical encoding decodes
+        let cases : [(u16, &[u8], &[u8]); 6] = [
+            (0, &[0x00], &[0x80, 0x00]),
+            (0, &[0x00], &[0x80, 0x80, 0x00]),
+            (0x7F, &[0x7F], &[0xFF, 0x00]),
+            (0x7F, &[0x7F], &[0xFF, 0x80, 0x00]),
+            (0x80, &[0x80, 0x01], &[0x80, 0x81, 0x00]),
+            (0x3FFF, &[0xFF, 0x7F], &[0xFF, 0xFF, 0x00])
+        ];
+
+        for (value, canonical, overlong) in cases {
+            assert_eq!(decode_compact_u16(&mut &canonical[..]).unwrap(), value);
+            assert!(
+                matches!(decode_compact_u16(&mut &overlong[..]), Err(TxError::InvalidCompactU16(_))),
+                "{:x?} accepted as {:#x}",
+                overlong,
+                value
+            );
+        }
 
-                        // The only other possibility is Err(None) => incomplete data, so continue reading lines
-                        Err(None) => ()
-                    }
-                },
-                Err(e) => {
-                    if line.len() == 0 {
-                        eprintln!("  Invalid Base64 input: {}", e);
-                        eprintln!("\n  Clearing tx data, start again.");
-                        break;
-                    }
-                },
-            }
+        // Values which don't fit in 16 bits
+        for bytes in [&[0xFF, 0xFF, 0x04][..], &[0x80, 0x80, 0x80]] {
+            assert!(matches!(decode_compact_u16(&mut &bytes[..]), Err(TxError::InvalidCompactU16(_))));
         }
+    }
 
-        // no_prompt stops after the first transaction
-        if no_prompt {
-            println!("");
-            break;
+    #[test]
+    fn compact_u16_truncated_input()
+    {
+        for bytes in [&[][..], &[0x80], &[0xFF, 0x80]] {
+            assert!(matches!(decode_compact_u16(&mut &bytes[..]), Err(TxError::Truncated)));
         }
     }
-}
 
-impl std::fmt::Display for Pubkey
-{
-    fn fmt(
-        &self,
-        f : &mut std::fmt::Formatter
-    ) -> std::fmt::Result
+    // A transaction of the System program alone, paid for by the key of seed 1, with instructions_count instructions
+    // that each do nothing and addresses_count - 2 more read-only accounts
+    fn transaction_with_counts(
+        addresses_count : u8,
+        instructions_count : u16
+    ) -> Transaction
     {
-        write!(f, "{}", bs58::encode(self.0).into_string())
+        Transaction {
+            signed_read_write_addresses : vec![unsigned(&Pubkey(test_keypair(1).public.to_bytes()))],
+            signed_read_only_addresses : vec![],
+            unsigned_read_write_addresses : vec![],
+            unsigned_read_only_addresses : std::iter::once(SYSTEM_PROGRAM_ADDRESS)
+                .chain((2..addresses_count).map(|i| Address([i; 32])))
+                .collect(),
+            recent_blockhash : Some(Sha256Digest([1_u8; 32])),
+            instructions : (0..instructions_count)
+                .map(|_| Instruction { program_address : SYSTEM_PROGRAM_ADDRESS, addresses : vec![], data : vec![] })
+                .collect()
+        }
     }
-}
 
-impl std::fmt::Display for Address
-{
-    fn fmt(
-        &self,
-        f : &mut std::fmt::Formatter
-    ) -> std::fmt::Result
+    fn decode_encoded(tx : &Transaction) -> Result<Transaction, TxError>
     {
-        write!(f, "{}", bs58::encode(self.0).into_string())
+        let mut encoded = vec![];
+        tx.encode(&mut encoded).unwrap();
+        Transaction::decode(&mut encoded.as_slice())
     }
-}
 
-impl PartialEq<Pubkey> for Address
-{
-    fn eq(
-        &self,
-        other : &Pubkey
-    ) -> bool
+    #[test]
+    fn decode_enforces_maximum_addresses_count()
     {
-        self.0 == other.0
+        let maximum = MAXIMUM_ADDRESSES_COUNT;
+
+        assert_eq!(decode_encoded(&transaction_with_counts(maximum, 1)).unwrap().address_count(), maximum as usize);
+
+        match decode_encoded(&transaction_with_counts(maximum + 1, 1)) {
+            Err(TxError::AtOffset { error, .. }) => assert!(
+                matches!(*error, TxError::TooManyAddresses { maximum : 37, actual : 38 }),
+                "unexpected error {}",
+                error
+            ),
+            _ => panic!("{} addresses accepted", maximum + 1)
+        }
+    }
+
+    #[test]
+    fn decode_enforces_maximum_instructions_count()
+    {
+        let maximum = MAXIMUM_INSTRUCTIONS_COUNT;
+
+        assert_eq!(decode_encoded(&transaction_with_counts(2, maximum)).unwrap().instructions.len(), maximum as usize);
+
+        match decode_encoded(&transaction_with_counts(2, maximum + 1)) {
+            Err(TxError::AtOffset { error, .. }) => assert!(
+                matches!(*error, TxError::TooManyInstructions { maximum : 397, actual : 398 }),
+                "unexpected error {}",
+                error
+            ),
+            _ => panic!("{} instructions accepted", maximum + 1)
+        }
+    }
+
+    // Every count in a transaction that decode accepts is kept well below 0x4000, but the instructions count of an
+    // encoded transaction can be that large, and decode must read all three bytes of it to find that there are too
+    // many instructions.  The error is detected just after them.
+    #[test]
+    fn three_byte_instructions_count_is_decoded()
+    {
+        let mut encoded = vec![];
+        transaction_with_counts(2, 0x4000).encode(&mut encoded).unwrap();
+
+        // Signatures count and one signature, message header, addresses count and two addresses, and the blockhash
+        let count_offset = 1 + 64 + 3 + 1 + (2 * 32) + 32;
+        assert_eq!(count_offset, 165);
+        assert_eq!(encoded[count_offset..(count_offset + 3)], [0x80, 0x80, 0x01]);
+
+        match Transaction::decode(&mut encoded.as_slice()) {
+            Err(TxError::AtOffset { offset, error }) => {
+                // Just past the three bytes of the count
+                assert_eq!(offset, 168);
+                assert!(
+                    matches!(*error, TxError::TooManyInstructions { maximum : 397, actual : 0x4000 }),
+                    "unexpected error {}",
+                    error
+                );
+            },
+            _ => panic!("0x4000 instructions accepted")
+        }
     }
 }